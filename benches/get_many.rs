@@ -0,0 +1,71 @@
+//! Compares fetching the medicines of a schedule one GET at a time, as
+//! `MedicineRepository::get_by_id` does, with a single MGET, as `get_many` does.
+//!
+//! Needs a Redis on `localhost:6379`, or the one in `REDIS_URL`:
+//!
+//! ```bash
+//! cargo bench --bench get_many
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use redis::AsyncCommands;
+
+const PREFIX: &str = "bench:medicine:";
+
+fn medicine(id: &str) -> String {
+    serde_json::json!({
+        "id": id,
+        "name": format!("Medicine {}", id),
+        "dose": 500.0,
+        "unit": "mg",
+        "stock": 100.0,
+        "batches": [],
+    }).to_string()
+}
+
+fn get_many(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
+    let client = redis::Client::open(url).unwrap();
+    let conn = runtime.block_on(client.get_multiplexed_async_connection())
+        .expect("The benchmark needs a running Redis");
+
+    let mut group = c.benchmark_group("get_medicines");
+    for count in [5, 50] {
+        let keys: Vec<String> = (0..count).map(|i| format!("{}{}", PREFIX, i)).collect();
+        runtime.block_on(async {
+            let mut conn = conn.clone();
+            for (i, key) in keys.iter().enumerate() {
+                let _: () = conn.set(key, medicine(&i.to_string())).await.unwrap();
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::new("one_by_one", count), &keys, |b, keys| {
+            b.to_async(&runtime).iter(|| async {
+                let mut conn = conn.clone();
+                for key in keys {
+                    let value: Option<String> = conn.get(key).await.unwrap();
+                    serde_json::from_str::<serde_json::Value>(&value.unwrap()).unwrap();
+                }
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("mget", count), &keys, |b, keys| {
+            b.to_async(&runtime).iter(|| async {
+                let mut conn = conn.clone();
+                let values: Vec<Option<String>> = conn.mget(keys).await.unwrap();
+                for value in values {
+                    serde_json::from_str::<serde_json::Value>(&value.unwrap()).unwrap();
+                }
+            });
+        });
+
+        runtime.block_on(async {
+            let mut conn = conn.clone();
+            let _: () = conn.del(&keys).await.unwrap();
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_many);
+criterion_main!(benches);
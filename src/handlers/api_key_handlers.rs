@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::Utc;
+use std::sync::Arc;
+use crate::auth::AuthenticatedUser;
+use crate::models::{ApiKey, ApiKeyInfo, ApiKeyRequest, NewApiKey, is_valid_scope};
+use super::{ApiError, AppState, IdPath};
+
+pub fn api_key_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api-keys", post(create_api_key))
+        .route("/api-keys", get(get_all_api_keys))
+        .route("/api-keys/:id", delete(delete_api_key))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    tag = "api-keys",
+    request_body = ApiKeyRequest,
+    responses(
+        (status = 200, description = "The key, its secret is only returned here", body = NewApiKey),
+        (status = 400, description = "Unknown scope"),
+    )
+)]
+async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(request): Json<ApiKeyRequest>,
+) -> Result<Json<NewApiKey>, ApiError> {
+    tracing::info!("POST /api-keys called");
+    
+    if let Some(scope) = request.scopes.iter().find(|scope| !is_valid_scope(scope)) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, serde_json::json!({
+            "reason": format!("Unknown scope {}", scope),
+        })));
+    }
+    
+    let (api_key, secret) = ApiKey::generate(request.name, user.subject, request.scopes, request.expires_at, Utc::now());
+    state.api_key_repo.save(&api_key).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(NewApiKey { key: api_key.info(), secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    tag = "api-keys",
+    responses(
+        (status = 200, description = "The keys of the user", body = Vec<ApiKeyInfo>),
+    )
+)]
+async fn get_all_api_keys(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<ApiKeyInfo>>, StatusCode> {
+    tracing::info!("GET /api-keys called");
+    
+    let api_keys = state.api_key_repo.get_by_subject(&user.subject).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(api_keys.iter().map(|api_key| api_key.info()).collect()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api-keys/{id}",
+    tag = "api-keys",
+    params(("id" = String, Path, description = "Id of the key")),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 404, description = "The user has no key with this id"),
+    )
+)]
+async fn delete_api_key(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<StatusCode, StatusCode> {
+    tracing::info!("DELETE /api-keys/{}", id);
+    
+    // Users can only revoke their own keys
+    state.api_key_repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|api_key| api_key.subject == user.subject)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    state.api_key_repo.delete(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
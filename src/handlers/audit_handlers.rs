@@ -0,0 +1,49 @@
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+use crate::models::{AuditEntry, AuditQuery, parse_audit_time};
+use super::{AppState, ProfileScope};
+
+pub fn audit_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/audit", get(get_audit_log))
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Changes to the profile, oldest first", body = Vec<AuditEntry>),
+        (status = 400, description = "Invalid time"),
+    )
+)]
+async fn get_audit_log(
+    scope: ProfileScope,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    tracing::info!("GET /audit called");
+    
+    let from = query.from.as_ref()
+        .map(|from| parse_audit_time(from, false).ok_or(StatusCode::BAD_REQUEST))
+        .transpose()?;
+    let to = query.to.as_ref()
+        .map(|to| parse_audit_time(to, true).ok_or(StatusCode::BAD_REQUEST))
+        .transpose()?;
+    
+    // Each profile has a log of its own, the default profile's also holds the entries
+    // of other profiles written before that
+    let entries = scope.repos.audit_repo.get_range(from, to).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .filter(|entry| entry.profile_id == scope.profile_id && query.matches(entry))
+        .collect();
+    
+    Ok(Json(entries))
+}
@@ -0,0 +1,66 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::models::{schedules_to_ics, CalendarSubscription};
+use crate::repositories::Repositories;
+use super::AppState;
+
+const CALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+#[derive(Debug, Deserialize)]
+struct TokenPath {
+    token: String,
+}
+
+/// The calendar feeds, for calendar apps subscribing with the token of a profile
+/// instead of logging in.
+pub fn calendar_feed_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/calendar/:token/schedules.ics", get(get_calendar_feed))
+}
+
+/// The schedules of a profile as an iCalendar file, starting today.
+pub(super) async fn calendar_response(repos: &Repositories, today: NaiveDate) -> Result<Response, StatusCode> {
+    let schedules = repos.schedule_repo.get_all().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let medicine_ids: Vec<_> = schedules.iter().map(|schedule| schedule.medicine_id.clone()).collect();
+    let medicines = repos.medicine_repo.get_many(&medicine_ids).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let ics = schedules_to_ics(&schedules, &medicines, today, Utc::now());
+    Ok(([(header::CONTENT_TYPE, CALENDAR_CONTENT_TYPE)], ics).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/calendar/{token}/schedules.ics",
+    tag = "schedules",
+    params(("token" = String, Path, description = "Token of the calendar subscription of a profile")),
+    responses(
+        (status = 200, description = "The schedules of the profile as recurring events", body = String, content_type = "text/calendar"),
+        (status = 404, description = "Unknown or revoked token"),
+    ),
+    security(())
+)]
+async fn get_calendar_feed(
+    State(state): State<Arc<AppState>>,
+    Path(TokenPath { token }): Path<TokenPath>,
+) -> Result<Response, StatusCode> {
+    tracing::info!("GET /v1/calendar/:token/schedules.ics called");
+    
+    let profile_id = CalendarSubscription::profile_from_token(&token).ok_or(StatusCode::NOT_FOUND)?;
+    let subscription = state.calendar_repo.get_by_profile(profile_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|subscription| subscription.verify(&token))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    let repos = Repositories::new(&state.redis, &state.profile_prefix(&subscription.profile_id));
+    calendar_response(&repos, state.today()).await
+}
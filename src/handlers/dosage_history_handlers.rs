@@ -1,54 +1,122 @@
 use axum::{
-    extract::{Path, State},
+    extract::Path,
     http::StatusCode,
     response::Json,
     routing::{delete, get, post},
     Router,
 };
 use std::sync::Arc;
-use crate::models::{DosageHistory, ApiDosageHistory};
-use crate::repositories::DosageHistoryRepository;
+use crate::models::{Action, DosageHistory, ApiDosageHistory, PrnLimitExceeded, WithWarnings};
+use super::{ApiError, AppState, IdPath, ProfileScope};
 
-pub fn dosage_history_routes() -> Router<Arc<DosageHistoryRepository>> {
+pub fn dosage_history_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/dosage-history", post(create_dosage_history))
         .route("/dosage-history", get(get_all_dosage_history))
         .route("/dosage-history/:id", delete(delete_dosage_history))
 }
 
+#[utoipa::path(
+    post,
+    path = "/dosage-history",
+    tag = "dosage-history",
+    request_body = ApiDosageHistory,
+    responses(
+        (status = 200, description = "The recorded dose, with warnings about exceeded limits of an as-needed medicine that only warns", body = WithWarnings<DosageHistory>),
+        (status = 400, description = "Invalid date or time, an amount that is not positive, or an unknown medicine"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 422, description = "The dose exceeds the limits of an as-needed medicine", body = PrnLimitExceeded),
+    )
+)]
 async fn create_dosage_history(
-    State(repo): State<Arc<DosageHistoryRepository>>,
+    scope: ProfileScope,
     Json(api_history): Json<ApiDosageHistory>,
-) -> Result<Json<DosageHistory>, StatusCode> {
+) -> Result<Json<WithWarnings<DosageHistory>>, ApiError> {
     tracing::info!("POST /dosage-history called");
     
-    let id = repo.create(api_history).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    scope.require(Action::LogDose)?;
+    
+    let repo = &scope.repos.dosage_history_repo;
+    let datetime = api_history.datetime()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !api_history.amount.is_finite() || api_history.amount <= 0.0 {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
     
-    let history = repo.get_by_id(&id).await
+    // The medicine must belong to the same profile
+    let medicine = scope.repos.medicine_repo.get_by_id(&api_history.medicine_id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    
+    // Check as-needed medicines against their safety limits
+    let mut warnings = Vec::new();
+    if let Some(prn) = medicine.prn {
+        let previous = repo.get_by_medicine_id(&api_history.medicine_id).await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        warnings = prn.check(&previous, datetime, api_history.amount);
+        if !warnings.is_empty() && !prn.warn_only {
+            tracing::warn!("Dose of {} refused, PRN limits exceeded", api_history.medicine_id);
+            return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, PrnLimitExceeded {
+                medicine_id: api_history.medicine_id.clone(),
+                violations: warnings,
+                next_allowed: prn.next_allowed(&previous, datetime),
+            }));
+        }
+    }
+    
+    let (history, store_dose) = repo.prepare_create(api_history)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    // Taking a dose uses up stock, first-expiry-first-out, the dose is stored along with it
+    let consumed = scope.repos.medicine_repo.consume_stock(&history.medicine_id, history.amount, &store_dose).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !consumed {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
     
-    Ok(Json(history))
+    Ok(Json(WithWarnings::new(history, warnings)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/dosage-history",
+    tag = "dosage-history",
+    responses(
+        (status = 200, description = "All doses taken", body = Vec<DosageHistory>),
+    )
+)]
 async fn get_all_dosage_history(
-    State(repo): State<Arc<DosageHistoryRepository>>,
+    scope: ProfileScope,
 ) -> Result<Json<Vec<DosageHistory>>, StatusCode> {
     tracing::info!("GET /dosage-history called");
     
+    let repo = &scope.repos.dosage_history_repo;
     let histories = repo.get_all().await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(histories))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/dosage-history/{id}",
+    tag = "dosage-history",
+    params(("id" = String, Path, description = "Id of the dose")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No dose with this id"),
+    )
+)]
 async fn delete_dosage_history(
-    State(repo): State<Arc<DosageHistoryRepository>>,
-    Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<StatusCode, ApiError> {
     tracing::info!("DELETE /dosage-history/{}", id);
     
+    scope.require(Action::Manage)?;
+    
+    let repo = &scope.repos.dosage_history_repo;
     // Check if history exists
     repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
@@ -0,0 +1,103 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, put},
+    Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::models::{Action, ApiGrant, Grant};
+use super::{ApiError, AppState, ProfileScope};
+
+#[derive(Debug, Deserialize)]
+struct SubjectPath {
+    subject: String,
+}
+
+pub fn grant_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/grants", get(get_all_grants))
+        .route("/grants/:subject", put(put_grant))
+        .route("/grants/:subject", delete(delete_grant))
+}
+
+#[utoipa::path(
+    get,
+    path = "/grants",
+    tag = "grants",
+    responses(
+        (status = 200, description = "All grants on the profile", body = Vec<Grant>),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
+async fn get_all_grants(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<Json<Vec<Grant>>, ApiError> {
+    tracing::info!("GET /grants called");
+    
+    scope.require(Action::Manage)?;
+    let grants = state.grant_repo.get_by_profile(&scope.profile_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(grants))
+}
+
+#[utoipa::path(
+    put,
+    path = "/grants/{subject}",
+    tag = "grants",
+    params(("subject" = String, Path, description = "Subject of the user given the role")),
+    request_body = ApiGrant,
+    responses(
+        (status = 200, description = "The grant", body = Grant),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
+async fn put_grant(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Path(SubjectPath { subject }): Path<SubjectPath>,
+    Json(api_grant): Json<ApiGrant>,
+) -> Result<Json<Grant>, ApiError> {
+    tracing::info!("PUT /grants/{}", subject);
+    
+    scope.require(Action::Manage)?;
+    let grant = api_grant.to_grant(scope.profile_id.clone(), subject);
+    state.grant_repo.save(&grant).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::info!("{} granted {:?} on profile {} to {}", scope.user.subject, grant.role, grant.profile_id, grant.subject);
+    
+    Ok(Json(grant))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/grants/{subject}",
+    tag = "grants",
+    params(("subject" = String, Path, description = "Subject of the user losing the role")),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "The subject has no grant on the profile"),
+    )
+)]
+async fn delete_grant(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Path(SubjectPath { subject }): Path<SubjectPath>,
+) -> Result<StatusCode, ApiError> {
+    tracing::info!("DELETE /grants/{}", subject);
+    
+    scope.require(Action::Manage)?;
+    state.grant_repo.get(&scope.profile_id, &subject).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    state.grant_repo.delete(&scope.profile_id, &subject).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::info!("{} revoked the grant on profile {} of {}", scope.user.subject, scope.profile_id, subject);
+    
+    Ok(StatusCode::NO_CONTENT)
+}
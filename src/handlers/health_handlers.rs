@@ -0,0 +1,83 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::models::{HealthCheck, HealthStatus, Readiness};
+use super::AppState;
+
+/// How long the readiness check waits for Redis to answer.
+const STORAGE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Health endpoints for load balancers and orchestrators, served without authentication.
+pub fn health_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/health", get(live))
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+}
+
+/// The process is running and serving requests, whatever state its dependencies are in.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "The process is running"),
+    )
+)]
+async fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Checks the storage backend and the background workers, answering 503 when
+/// storage isn't reachable, a worker stopped or the server is shutting down.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Ready to serve requests", body = Readiness),
+        (status = 503, description = "A dependency is down or the server is shutting down", body = Readiness),
+    )
+)]
+async fn ready(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<Readiness>) {
+    let server = if state.shutdown.is_cancelled() {
+        HealthCheck::down("Shutting down".to_string())
+    } else {
+        HealthCheck { status: HealthStatus::Up, latency_ms: None, error: None }
+    };
+    let storage = match tokio::time::timeout(STORAGE_CHECK_TIMEOUT, state.redis.ping()).await {
+        Ok(Ok(latency)) => HealthCheck::up(latency),
+        Ok(Err(e)) => {
+            tracing::warn!("GET /health/ready: storage is unreachable: {}", e);
+            HealthCheck::down("Unreachable".to_string())
+        }
+        Err(_) => HealthCheck::down(format!("No answer within {} seconds", STORAGE_CHECK_TIMEOUT.as_secs())),
+    };
+    let workers = match state.workers.stopped() {
+        0 => HealthCheck { status: HealthStatus::Up, latency_ms: None, error: None },
+        stopped => HealthCheck::down(format!("{} of {} stopped", stopped, state.workers.spawned())),
+    };
+    
+    let readiness = Readiness::new(BTreeMap::from([
+        ("server".to_string(), server),
+        ("storage".to_string(), storage),
+        ("workers".to_string(), workers),
+    ]));
+    if !readiness.is_ready() {
+        tracing::warn!("GET /health/ready: not ready, {:?}", readiness.checks);
+    }
+    
+    let status = if readiness.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(readiness))
+}
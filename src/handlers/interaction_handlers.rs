@@ -0,0 +1,50 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::models::{InteractionFinding, Medicine};
+use crate::repositories::Repositories;
+use super::{AppState, ProfileScope};
+
+pub fn interaction_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/interactions", get(get_interactions))
+}
+
+/// The medicines currently in use: those that are scheduled or taken as needed.
+pub(super) async fn current_regimen(repos: &Repositories) -> anyhow::Result<Vec<Medicine>> {
+    let scheduled: HashSet<String> = repos.schedule_repo.get_all().await?
+        .into_iter()
+        .map(|schedule| schedule.medicine_id)
+        .collect();
+    let medicines = repos.medicine_repo.get_all().await?
+        .into_iter()
+        .filter(|medicine| scheduled.contains(&medicine.id) || medicine.prn.is_some())
+        .collect();
+    Ok(medicines)
+}
+
+#[utoipa::path(
+    get,
+    path = "/interactions",
+    tag = "interactions",
+    responses(
+        (status = 200, description = "Interactions and duplicate ingredients in the current regimen", body = Vec<InteractionFinding>),
+    )
+)]
+async fn get_interactions(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<Json<Vec<InteractionFinding>>, StatusCode> {
+    tracing::info!("GET /interactions called");
+    
+    let regimen = current_regimen(&scope.repos).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(state.interactions.check_regimen(&regimen)))
+}
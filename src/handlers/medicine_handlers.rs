@@ -5,26 +5,52 @@ use axum::{
     routing::{delete, get, post, put},
     Router,
 };
+use chrono::{Days, NaiveDate, Utc};
 use std::sync::Arc;
-use crate::models::{Medicine, ApiMedicine};
-use crate::repositories::MedicineRepository;
+use crate::models::{
+    Action, Medicine, ApiMedicine, ApiStockCount, ExpiringStock, LedgerEntry, NextAllowedDose, NoRefillLeft, Reconciliation,
+    StockBatch, WithWarnings, parse_period_days
+};
+use super::{ApiError, AppState, IdPath, ProfileScope};
+use super::interaction_handlers::current_regimen;
 
-pub fn medicine_routes() -> Router<Arc<MedicineRepository>> {
+pub fn medicine_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/medicines", post(create_medicine))
         .route("/medicines", get(get_all_medicines))
+        .route("/medicines/expiring", get(get_expiring_stock))
         .route("/medicines/:id", get(get_medicine_by_id))
         .route("/medicines/:id", put(update_medicine))
         .route("/medicines/:id", delete(delete_medicine))
         .route("/medicines/:id/addStock", post(add_stock))
+        .route("/medicines/:id/next-allowed", get(get_next_allowed_dose))
+        .route("/medicines/:id/stock-ledger", get(get_stock_ledger))
+        .route("/medicines/:id/reconcile", post(reconcile_stock))
+        .route("/medicines/:id/dispose-expired", post(dispose_expired_stock))
 }
 
+#[utoipa::path(
+    post,
+    path = "/medicines",
+    tag = "medicines",
+    request_body = ApiMedicine,
+    responses(
+        (status = 200, description = "The created medicine, with warnings about interactions with the current regimen", body = WithWarnings<Medicine>),
+        (status = 400, description = "The PRN limits can't be met"),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
 async fn create_medicine(
-    State(repo): State<Arc<MedicineRepository>>,
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
     Json(api_medicine): Json<ApiMedicine>,
-) -> Result<Json<Medicine>, StatusCode> {
+) -> Result<Json<WithWarnings<Medicine>>, ApiError> {
     tracing::info!("POST /medicines called");
     
+    scope.require(Action::Manage)?;
+    validate_prn(&api_medicine)?;
+    
+    let repo = &scope.repos.medicine_repo;
     let id = repo.create(api_medicine).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
@@ -32,26 +58,62 @@ async fn create_medicine(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
     
-    Ok(Json(medicine))
+    // Warn about interactions with the medicines currently in use
+    let regimen = current_regimen(&scope.repos).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let warnings = state.interactions.check(&medicine, &regimen)
+        .iter()
+        .map(|finding| finding.to_warning())
+        .collect();
+    
+    Ok(Json(WithWarnings::new(medicine, warnings)))
+}
+
+/// Refuses PRN limits that can't be met with 400.
+fn validate_prn(api_medicine: &ApiMedicine) -> Result<(), ApiError> {
+    match api_medicine.prn.as_ref().map(|prn| prn.validate()) {
+        Some(Err(reason)) => Err(ApiError::new(StatusCode::BAD_REQUEST, serde_json::json!({ "reason": reason }))),
+        _ => Ok(()),
+    }
 }
 
+#[utoipa::path(
+    get,
+    path = "/medicines",
+    tag = "medicines",
+    responses(
+        (status = 200, description = "All medicines", body = Vec<Medicine>),
+    )
+)]
 async fn get_all_medicines(
-    State(repo): State<Arc<MedicineRepository>>,
+    scope: ProfileScope,
 ) -> Result<Json<Vec<Medicine>>, StatusCode> {
     tracing::info!("GET /medicines called");
     
+    let repo = &scope.repos.medicine_repo;
     let medicines = repo.get_all().await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(medicines))
 }
 
+#[utoipa::path(
+    get,
+    path = "/medicines/{id}",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    responses(
+        (status = 200, description = "The medicine", body = Medicine),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
 async fn get_medicine_by_id(
-    State(repo): State<Arc<MedicineRepository>>,
-    Path(id): Path<String>,
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<Medicine>, StatusCode> {
     tracing::info!("GET /medicines/{}", id);
     
+    let repo = &scope.repos.medicine_repo;
     let medicine = repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -59,13 +121,30 @@ async fn get_medicine_by_id(
     Ok(Json(medicine))
 }
 
+#[utoipa::path(
+    put,
+    path = "/medicines/{id}",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    request_body = ApiMedicine,
+    responses(
+        (status = 200, description = "The updated medicine", body = Medicine),
+        (status = 400, description = "The PRN limits can't be met"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
 async fn update_medicine(
-    State(repo): State<Arc<MedicineRepository>>,
-    Path(id): Path<String>,
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
     Json(api_medicine): Json<ApiMedicine>,
-) -> Result<Json<Medicine>, StatusCode> {
+) -> Result<Json<Medicine>, ApiError> {
     tracing::info!("PUT /medicines/{}", id);
     
+    scope.require(Action::Manage)?;
+    validate_prn(&api_medicine)?;
+    
+    let repo = &scope.repos.medicine_repo;
     // Check if medicine exists
     repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
@@ -81,12 +160,26 @@ async fn update_medicine(
     Ok(Json(medicine))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/medicines/{id}",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
 async fn delete_medicine(
-    State(repo): State<Arc<MedicineRepository>>,
-    Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<StatusCode, ApiError> {
     tracing::info!("DELETE /medicines/{}", id);
     
+    scope.require(Action::Manage)?;
+    
+    let repo = &scope.repos.medicine_repo;
     // Check if medicine exists
     repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
@@ -98,23 +191,68 @@ async fn delete_medicine(
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    post,
+    path = "/medicines/{id}/addStock",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine"), ("amount" = Option<f64>, Query, description = "Amount added, defaults to the quantity per fill of the prescription"), ("prescription_id" = Option<String>, Query, description = "Prescription that was filled, which uses up one of its refills"), ("lot" = Option<String>, Query, description = "Lot number of the batch"), ("expiry" = Option<NaiveDate>, Query, description = "Expiry date of the batch")),
+    responses(
+        (status = 200, description = "The medicine with its new stock", body = Medicine),
+        (status = 400, description = "No positive amount or prescription given, or the prescription is not for this medicine"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No medicine with this id"),
+        (status = 409, description = "The prescription has no refills left"),
+    )
+)]
 async fn add_stock(
-    State(repo): State<Arc<MedicineRepository>>,
-    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
     axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<Medicine>, StatusCode> {
+) -> Result<Json<Medicine>, ApiError> {
     tracing::info!("POST /medicines/{}/addStock", id);
     
-    let amount = params.get("amount")
-        .ok_or(StatusCode::BAD_REQUEST)?
-        .parse::<f64>()
+    scope.require(Action::AddStock)?;
+    
+    let repo = &scope.repos.medicine_repo;
+    let today = state.today();
+    
+    // Filling a prescription uses up one of its refills
+    let prescription = match params.get("prescription_id") {
+        Some(prescription_id) => {
+            let prescription = scope.repos.prescription_repo.get_by_id(prescription_id).await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .filter(|prescription| prescription.medicine_id == id)
+                .ok_or(StatusCode::BAD_REQUEST)?;
+            Some(prescription.use_refill(today).ok_or(StatusCode::CONFLICT)?)
+        }
+        None => None,
+    };
+    
+    let amount = match (params.get("amount"), &prescription) {
+        (Some(amount), _) => amount.parse::<f64>().map_err(|_| StatusCode::BAD_REQUEST)?,
+        (None, Some(prescription)) => prescription.quantity_per_fill,
+        (None, None) => return Err(StatusCode::BAD_REQUEST.into()),
+    };
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let expiry_date = params.get("expiry")
+        .map(|expiry| NaiveDate::parse_from_str(expiry, "%Y-%m-%d"))
+        .transpose()
         .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let batch = StockBatch::new(amount, params.get("lot").cloned(), expiry_date, today);
     
-    let success = repo.add_stock(&id, amount).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // The refill is used up in the same transaction, so two fills can't both take the last one
+    let refill = prescription.map(|prescription| scope.repos.prescription_repo.refill(&prescription.id, &id, today));
+    let success = repo.add_stock(&id, batch, &refill).await
+        .map_err(|e| match e.downcast_ref::<NoRefillLeft>() {
+            Some(_) => StatusCode::CONFLICT,
+            None => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
     
     if !success {
-        return Err(StatusCode::NOT_FOUND);
+        return Err(StatusCode::NOT_FOUND.into());
     }
     
     let medicine = repo.get_by_id(&id).await
@@ -122,4 +260,152 @@ async fn add_stock(
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(medicine))
-} 
\ No newline at end of file
+}
+
+#[utoipa::path(
+    get,
+    path = "/medicines/expiring",
+    tag = "medicines",
+    params(("within" = Option<String>, Query, description = "Period in days or weeks such as `30d`, `4w` or `30`, of at most 3650 days, defaults to the configured period")),
+    responses(
+        (status = 200, description = "Batches expiring within the period", body = Vec<ExpiringStock>),
+        (status = 400, description = "Invalid period"),
+    )
+)]
+async fn get_expiring_stock(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<Vec<ExpiringStock>>, StatusCode> {
+    tracing::info!("GET /medicines/expiring called");
+    
+    let repo = &scope.repos.medicine_repo;
+    let days = params.get("within")
+        .map(|within| parse_period_days(within).ok_or(StatusCode::BAD_REQUEST))
+        .transpose()?
+        .unwrap_or(state.expiring_within_days);
+    let before = state.today().checked_add_days(Days::new(days as u64))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    
+    let expiring = repo.get_expiring(before).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(expiring))
+}
+
+#[utoipa::path(
+    get,
+    path = "/medicines/{id}/next-allowed",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    responses(
+        (status = 200, description = "When the next as-needed dose may be taken", body = NextAllowedDose),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
+async fn get_next_allowed_dose(
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<NextAllowedDose>, StatusCode> {
+    tracing::info!("GET /medicines/{}/next-allowed", id);
+    
+    let repo = &scope.repos.medicine_repo;
+    let medicine = repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    let history = scope.repos.dosage_history_repo.get_by_medicine_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(NextAllowedDose::new(id, medicine.prn.as_ref(), &history, Utc::now())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/medicines/{id}/stock-ledger",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    responses(
+        (status = 200, description = "All stock movements of the medicine", body = Vec<LedgerEntry>),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
+async fn get_stock_ledger(
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<Vec<LedgerEntry>>, StatusCode> {
+    tracing::info!("GET /medicines/{}/stock-ledger", id);
+    
+    let repo = &scope.repos.medicine_repo;
+    // Check if medicine exists
+    repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    let ledger = repo.get_ledger(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ledger))
+}
+
+#[utoipa::path(
+    post,
+    path = "/medicines/{id}/reconcile",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    request_body = ApiStockCount,
+    responses(
+        (status = 200, description = "The difference between the counted and expected stock", body = Reconciliation),
+        (status = 400, description = "Negative count"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
+async fn reconcile_stock(
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Json(count): Json<ApiStockCount>,
+) -> Result<Json<Reconciliation>, ApiError> {
+    tracing::info!("POST /medicines/{}/reconcile", id);
+    
+    scope.require(Action::AddStock)?;
+    
+    if count.counted < 0.0 {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    
+    let repo = &scope.repos.medicine_repo;
+    let reconciliation = repo.reconcile(&id, count.counted, count.reason).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    Ok(Json(reconciliation))
+}
+
+#[utoipa::path(
+    post,
+    path = "/medicines/{id}/dispose-expired",
+    tag = "medicines",
+    params(("id" = String, Path, description = "Id of the medicine")),
+    responses(
+        (status = 200, description = "The disposals of the expired batches", body = Vec<LedgerEntry>),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No medicine with this id"),
+    )
+)]
+async fn dispose_expired_stock(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<Vec<LedgerEntry>>, ApiError> {
+    tracing::info!("POST /medicines/{}/dispose-expired", id);
+    
+    scope.require(Action::AddStock)?;
+    
+    let repo = &scope.repos.medicine_repo;
+    let disposals = repo.dispose_expired(&id, state.today()).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    Ok(Json(disposals))
+}
@@ -0,0 +1,93 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::models::{daily_usage, missed_doses, DEFAULT_PROFILE_ID};
+use crate::repositories::Repositories;
+use super::AppState;
+
+/// How often the domain gauges are brought up to date.
+const DOMAIN_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn metrics_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/metrics", get(get_metrics))
+}
+
+/// Counts requests and records their duration per route and status.
+pub async fn track_metrics(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request.extensions().get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    tracing::Span::current().record("route", route.as_str());
+
+    let response = next.run(request).await;
+
+    let labels = [("method", method), ("route", route), ("status", response.status().as_u16().to_string())];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(started.elapsed().as_secs_f64());
+    response
+}
+
+/// Metrics in the Prometheus text format. The domain gauges are kept up to date by
+/// `domain_metrics_worker`.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    security(()),
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    )
+)]
+async fn get_metrics(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render())
+}
+
+/// Brings the domain gauges up to date every minute, until shutdown. They are totals
+/// over all profiles, as `/metrics` is served without authentication.
+pub async fn domain_metrics_worker(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(DOMAIN_METRICS_INTERVAL);
+    // Also stops while updating, which waits on Redis
+    while state.shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+        if let Some(Err(e)) = state.shutdown.run_until_cancelled(record_domain_metrics(&state)).await {
+            tracing::warn!("Could not update the domain metrics: {}", e);
+        }
+    }
+}
+
+async fn record_domain_metrics(state: &AppState) -> anyhow::Result<()> {
+    let today = state.today();
+    let now = Utc::now().with_timezone(&state.timezone).time();
+
+    let mut profile_ids = vec![DEFAULT_PROFILE_ID.to_string()];
+    profile_ids.extend(state.profile_repo.get_all().await?.into_iter().map(|profile| profile.id));
+
+    let mut below_reorder_threshold = 0;
+    let mut missed = 0;
+    for profile_id in profile_ids {
+        let repos = Repositories::new(&state.redis, &state.profile_prefix(&profile_id));
+        let medicines = repos.medicine_repo.get_all().await?;
+        let schedules = repos.schedule_repo.get_all().await?;
+        let history = repos.dosage_history_repo.get_all().await?;
+
+        below_reorder_threshold += medicines.iter()
+            .filter(|medicine| medicine.needs_reorder(daily_usage(&schedules, &medicine.id), state.reorder_within_days))
+            .count();
+        missed += missed_doses(&schedules, &history, today, now, state.timezone);
+    }
+    metrics::gauge!("medicines_below_reorder_threshold").set(below_reorder_threshold as f64);
+    metrics::gauge!("doses_missed_today").set(missed as f64);
+    Ok(())
+}
@@ -1,3 +1,240 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, RawPathParams},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Json, Response},
+    middleware,
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use metrics_exporter_prometheus::PrometheusHandle;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use crate::auth::{require_auth, AuthenticatedUser, Authenticator};
+use crate::models::{Action, InteractionTable, ProfileId, Role, DEFAULT_PROFILE_ID};
+use crate::repositories::{ApiKeyRepository, Auditor, CalendarSubscriptionRepository, GrantRepository, ProfileRepository, RedisConnection, Repositories};
+
 pub mod medicine_handlers;
 pub mod schedule_handlers;
-pub mod dosage_history_handlers; 
\ No newline at end of file
+pub mod dosage_history_handlers;
+pub mod prescription_handlers;
+pub mod interaction_handlers;
+pub mod profile_handlers;
+pub mod grant_handlers;
+pub mod api_key_handlers;
+pub mod audit_handlers;
+pub mod calendar_handlers;
+pub mod health_handlers;
+pub mod metrics_handlers;
+pub mod openapi_handlers;
+pub mod v1;
+pub mod v2;
+
+/// Storage settings, authentication and reference data shared by all routes.
+pub struct AppState {
+    pub redis: RedisConnection,
+    pub namespace: String,
+    pub authenticator: Authenticator,
+    pub profile_repo: ProfileRepository,
+    pub grant_repo: GrantRepository,
+    pub api_key_repo: ApiKeyRepository,
+    pub calendar_repo: CalendarSubscriptionRepository,
+    pub interactions: InteractionTable,
+    /// Subject that is the patient of the default profile, which has no stored grants
+    /// of its own until this user adds them.
+    pub default_profile_owner: Option<String>,
+    /// Time zone the dates of the users are in, e.g. for what counts as today.
+    pub timezone: Tz,
+    /// Default window of `/medicines/expiring`.
+    pub expiring_within_days: i64,
+    /// Medicines whose stock lasts fewer days than this need to be reordered.
+    pub reorder_within_days: i64,
+    pub metrics: PrometheusHandle,
+    /// Cancelled when the server starts shutting down.
+    pub shutdown: CancellationToken,
+    pub workers: Workers,
+}
+
+/// Background tasks that run until the server shuts down, so any of them that
+/// stopped before then has failed.
+#[derive(Clone, Default)]
+pub struct Workers {
+    tracker: TaskTracker,
+    spawned: Arc<AtomicUsize>,
+}
+
+impl Workers {
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+        self.tracker.spawn(task);
+    }
+
+    pub fn spawned(&self) -> usize {
+        self.spawned.load(Ordering::Relaxed)
+    }
+
+    /// How many of the spawned workers are no longer running.
+    pub fn stopped(&self) -> usize {
+        self.spawned().saturating_sub(self.tracker.len())
+    }
+
+    /// Waits until all workers have stopped, none can be spawned afterwards.
+    pub async fn wait(&self) {
+        self.tracker.close();
+        self.tracker.wait().await;
+    }
+}
+
+impl AppState {
+    /// Prefix of the keys holding the data of a profile. The default profile keeps
+    /// its data directly under the namespace.
+    pub fn profile_prefix(&self, profile_id: &str) -> String {
+        if profile_id == DEFAULT_PROFILE_ID {
+            self.namespace.clone()
+        } else {
+            format!("{}{}:", self.namespace, profile_id)
+        }
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.timezone).date_naive()
+    }
+
+    /// The role `subject` was granted on a profile, if any.
+    pub async fn role_of(&self, profile_id: &str, subject: &str) -> anyhow::Result<Option<Role>> {
+        if profile_id == DEFAULT_PROFILE_ID && self.default_profile_owner.as_deref() == Some(subject) {
+            return Ok(Some(Role::Patient));
+        }
+        Ok(self.grant_repo.get(profile_id, subject).await?.map(|grant| grant.role))
+    }
+}
+
+/// All routes of the API. Models only change in a new version of the API, each in
+/// its own module and served under its own prefix. The version 1 routes are also
+/// served without a prefix for older clients. Only the health, metrics and
+/// documentation endpoints and the calendar feeds, which check their own token, can
+/// be used without authentication.
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .nest("/v1", v1::routes())
+        .nest("/v2", v2::routes())
+        .merge(v1::routes().route_layer(middleware::from_fn(v1::deprecated_alias)))
+        .route_layer(middleware::from_fn_with_state(state, require_auth))
+        .merge(health_handlers::health_routes())
+        .merge(metrics_handlers::metrics_routes())
+        .merge(openapi_handlers::openapi_routes())
+        .merge(calendar_handlers::calendar_feed_routes())
+        .route_layer(middleware::from_fn(metrics_handlers::track_metrics))
+}
+
+/// The profile a request works on, taken from the `:pid` path parameter, with the
+/// role of the authenticated user on it and the repositories holding that profile's
+/// data. Data of other profiles is not reachable through it. Users without a grant
+/// on the profile are refused with 403. Changes made through the repositories are
+/// recorded in the audit log under the user's name, other changes of the profile
+/// with `auditor`.
+pub struct ProfileScope {
+    pub profile_id: ProfileId,
+    pub user: AuthenticatedUser,
+    pub role: Role,
+    pub repos: Repositories,
+    pub auditor: Auditor,
+}
+
+impl ProfileScope {
+    /// Refuses the request with 403 when the user's role does not allow `action`.
+    pub fn require(&self, action: Action) -> Result<(), ApiError> {
+        self.role.check(action).map_err(ApiError::forbidden)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ProfileScope {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        let params = RawPathParams::from_request_parts(parts, state).await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let profile_id = match params.iter().find(|(key, _)| *key == "pid") {
+            Some((_, pid)) => {
+                state.profile_repo.get_by_id(pid).await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                    .ok_or(StatusCode::NOT_FOUND)?
+                    .id
+            }
+            None => DEFAULT_PROFILE_ID.to_string(),
+        };
+
+        let role = state.role_of(&profile_id, &user.subject).await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or_else(|| ApiError::forbidden("No access to this profile".to_string()))?;
+
+        let repos = Repositories::new(&state.redis, &state.profile_prefix(&profile_id));
+        let auditor = Auditor::new(repos.audit_repo.clone(), user.subject.clone(), profile_id.clone());
+        let repos = repos.with_auditor(auditor.clone());
+        Ok(Self { profile_id, user, role, repos, auditor })
+    }
+}
+
+/// Path of a single entity. Deserializing into a struct ignores the `pid` parameter
+/// of profile scoped routes.
+#[derive(Debug, Deserialize)]
+pub struct IdPath {
+    pub id: String,
+}
+
+/// Error response for handlers that need to explain why a request was refused,
+/// plain status codes convert into it.
+pub struct ApiError(Box<Response>);
+
+impl ApiError {
+    pub fn new<T: Serialize>(status: StatusCode, body: T) -> Self {
+        Self(Box::new((status, Json(body)).into_response()))
+    }
+
+    pub fn forbidden(reason: String) -> Self {
+        Self::new(StatusCode::FORBIDDEN, serde_json::json!({ "reason": reason }))
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self(Box::new(status.into_response()))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        *self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_workers_count_the_stopped_ones() {
+        let workers = Workers::default();
+        let shutdown = CancellationToken::new();
+        let running = shutdown.clone();
+        workers.spawn(async move { running.cancelled().await });
+        workers.spawn(async {});
+        tokio::task::yield_now().await;
+        
+        assert_eq!(workers.spawned(), 2);
+        assert_eq!(workers.stopped(), 1);
+        
+        shutdown.cancel();
+        workers.wait().await;
+        assert_eq!(workers.stopped(), 2);
+    }
+}
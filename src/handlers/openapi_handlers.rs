@@ -0,0 +1,354 @@
+use axum::Router;
+use std::sync::Arc;
+use utoipa::openapi::path::{Operation, ParameterBuilder, ParameterIn, PathItem};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Deprecated, OpenApi as OpenApiDocument, Required};
+use utoipa::{Modify, OpenApi, PartialSchema};
+use utoipa_swagger_ui::SwaggerUi;
+use super::{
+    api_key_handlers, audit_handlers, calendar_handlers, dosage_history_handlers, grant_handlers, health_handlers, interaction_handlers,
+    medicine_handlers, metrics_handlers, prescription_handlers, profile_handlers, schedule_handlers, v2, AppState,
+};
+
+/// Prefix under which the routes of a profile other than the default one are served.
+const PROFILE_PREFIX: &str = "/profiles/{pid}";
+
+/// The OpenAPI document at `/openapi.json` and Swagger UI at `/docs`, served without
+/// authentication.
+pub fn openapi_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", openapi()))
+}
+
+/// The version 1 routes working on the data of a profile, see `v1::profile_scoped_routes`.
+#[derive(OpenApi)]
+#[openapi(paths(
+    medicine_handlers::create_medicine,
+    medicine_handlers::get_all_medicines,
+    medicine_handlers::get_expiring_stock,
+    medicine_handlers::get_medicine_by_id,
+    medicine_handlers::update_medicine,
+    medicine_handlers::delete_medicine,
+    medicine_handlers::add_stock,
+    medicine_handlers::get_next_allowed_dose,
+    medicine_handlers::get_stock_ledger,
+    medicine_handlers::reconcile_stock,
+    medicine_handlers::dispose_expired_stock,
+    schedule_handlers::create_schedule,
+    schedule_handlers::get_all_schedules,
+    schedule_handlers::get_schedule_by_id,
+    schedule_handlers::update_schedule,
+    schedule_handlers::delete_schedule,
+    schedule_handlers::get_daily_schedule,
+    schedule_handlers::get_schedule_range,
+    schedule_handlers::get_schedule_calendar,
+    schedule_handlers::create_calendar_subscription,
+    schedule_handlers::delete_calendar_subscription,
+    schedule_handlers::import_ics,
+    dosage_history_handlers::create_dosage_history,
+    dosage_history_handlers::get_all_dosage_history,
+    dosage_history_handlers::delete_dosage_history,
+    prescription_handlers::create_prescription,
+    prescription_handlers::get_all_prescriptions,
+    prescription_handlers::get_refill_alerts,
+    prescription_handlers::get_prescription_by_id,
+    prescription_handlers::update_prescription,
+    prescription_handlers::delete_prescription,
+    interaction_handlers::get_interactions,
+    grant_handlers::get_all_grants,
+    grant_handlers::put_grant,
+    grant_handlers::delete_grant,
+    audit_handlers::get_audit_log,
+))]
+struct ProfileScopedApi;
+
+/// The other version 1 routes.
+#[derive(OpenApi)]
+#[openapi(paths(
+    profile_handlers::create_profile,
+    profile_handlers::get_all_profiles,
+    profile_handlers::get_profile_by_id,
+    profile_handlers::update_profile,
+    profile_handlers::delete_profile,
+    api_key_handlers::create_api_key,
+    api_key_handlers::get_all_api_keys,
+    api_key_handlers::delete_api_key,
+))]
+struct ApiV1;
+
+/// The version 2 routes working on the data of a profile.
+#[derive(OpenApi)]
+#[openapi(paths(
+    v2::schedule_handlers::get_daily_schedule,
+    v2::schedule_handlers::get_schedule_range,
+))]
+struct ProfileScopedApiV2;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(description = "Keeps track of medicines, their stock and when to take them."),
+    paths(
+        health_handlers::live,
+        health_handlers::ready,
+        metrics_handlers::get_metrics,
+        calendar_handlers::get_calendar_feed,
+    ),
+    modifiers(&SecuritySchemes),
+    security(("bearer" = []), ("api_key" = [])),
+)]
+struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(
+            HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build(),
+        ));
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(
+            ApiKeyValue::with_description("Authorization", "An API key as `ApiKey <secret>`"),
+        )));
+    }
+}
+
+/// The OpenAPI document of the whole API. The routes of a profile are listed for
+/// the default profile and again under `/profiles/{pid}`. The version 1 routes are
+/// also listed without a prefix, as deprecated.
+pub fn openapi() -> OpenApiDocument {
+    let v1 = ApiV1::openapi().merge_from(profile_scoped(ProfileScopedApi::openapi()));
+    ApiDoc::openapi()
+        .nest("/v1", v1.clone())
+        .nest("/v2", profile_scoped(ProfileScopedApiV2::openapi()))
+        .merge_from(deprecated(v1))
+}
+
+/// Marks all operations deprecated, for the unprefixed aliases of versioned routes.
+fn deprecated(mut openapi: OpenApiDocument) -> OpenApiDocument {
+    for item in openapi.paths.paths.values_mut() {
+        for operation in operations(item) {
+            operation.deprecated = Some(Deprecated::True);
+            rename_operation(operation, "unversioned");
+        }
+    }
+    openapi
+}
+
+/// The routes of a profile, served for the default profile and nested under
+/// `/profiles/{pid}`.
+fn profile_scoped(scoped: OpenApiDocument) -> OpenApiDocument {
+    let mut openapi = scoped.clone().nest(PROFILE_PREFIX, scoped);
+    let nested_prefix = format!("{}/", PROFILE_PREFIX);
+    for (path, item) in openapi.paths.paths.iter_mut() {
+        if path.starts_with(&nested_prefix) {
+            scope_to_profile(item);
+        }
+    }
+    openapi
+}
+
+/// Adds the profile id parameter to the operations of a nested path and keeps their
+/// operation ids unique.
+fn scope_to_profile(item: &mut PathItem) {
+    item.parameters.get_or_insert_with(Vec::new).push(
+        ParameterBuilder::new()
+            .name("pid")
+            .parameter_in(ParameterIn::Path)
+            .required(Required::True)
+            .description(Some("Id of the profile"))
+            .schema(Some(String::schema()))
+            .build(),
+    );
+    for operation in operations(item) {
+        rename_operation(operation, "for_profile");
+    }
+}
+
+fn operations(item: &mut PathItem) -> impl Iterator<Item = &mut Operation> {
+    [&mut item.get, &mut item.put, &mut item.post, &mut item.delete, &mut item.patch].into_iter().flatten()
+}
+
+/// Keeps the operation id unique when an operation is listed under several paths.
+fn rename_operation(operation: &mut Operation, suffix: &str) {
+    if let Some(id) = &operation.operation_id {
+        operation.operation_id = Some(format!("{}_{}", id, suffix));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::path::Path;
+
+    const PROFILE_SCOPED: &[&str] = &["", PROFILE_PREFIX, "/v1", "/v1/profiles/{pid}"];
+    const V1: &[&str] = &["", "/v1"];
+    const PROFILE_SCOPED_V2: &[&str] = &["/v2", "/v2/profiles/{pid}"];
+
+    /// Sources registering routes, by their path under `src/handlers`, and the prefixes
+    /// their routes are served under.
+    const ROUTE_SOURCES: [(&str, &str, &[&str]); 13] = [
+        ("medicine_handlers.rs", include_str!("medicine_handlers.rs"), PROFILE_SCOPED),
+        ("schedule_handlers.rs", include_str!("schedule_handlers.rs"), PROFILE_SCOPED),
+        ("dosage_history_handlers.rs", include_str!("dosage_history_handlers.rs"), PROFILE_SCOPED),
+        ("prescription_handlers.rs", include_str!("prescription_handlers.rs"), PROFILE_SCOPED),
+        ("interaction_handlers.rs", include_str!("interaction_handlers.rs"), PROFILE_SCOPED),
+        ("grant_handlers.rs", include_str!("grant_handlers.rs"), PROFILE_SCOPED),
+        ("audit_handlers.rs", include_str!("audit_handlers.rs"), PROFILE_SCOPED),
+        ("profile_handlers.rs", include_str!("profile_handlers.rs"), V1),
+        ("api_key_handlers.rs", include_str!("api_key_handlers.rs"), V1),
+        ("health_handlers.rs", include_str!("health_handlers.rs"), &[""]),
+        ("metrics_handlers.rs", include_str!("metrics_handlers.rs"), &[""]),
+        ("v2/schedule_handlers.rs", include_str!("v2/schedule_handlers.rs"), PROFILE_SCOPED_V2),
+        ("calendar_handlers.rs", include_str!("calendar_handlers.rs"), &[""]),
+    ];
+
+    /// Handler modules without API routes, this one only serves the document and the Swagger UI.
+    const NOT_SCANNED: [&str; 1] = ["openapi_handlers.rs"];
+
+    /// `/health` is an alias of `/health/live` kept for existing load balancers.
+    const UNDOCUMENTED: [&str; 1] = ["GET /health"];
+
+    const METHODS: [&str; 5] = ["get", "post", "put", "delete", "patch"];
+
+    /// The `.route("/path", method(handler))` calls of a source, as `METHOD /path`
+    /// in the OpenAPI path syntax. The calls may span several lines.
+    fn registered_routes(file: &str, source: &str) -> Vec<(String, String)> {
+        source.split(".route(")
+            .skip(1)
+            .map(|call| {
+                let (path, rest) = call.trim_start()
+                    .strip_prefix('"')
+                    .and_then(|call| call.split_once('"'))
+                    .unwrap_or_else(|| panic!("{}: the path of a `.route(` call is not a string literal", file));
+                let method = rest.trim_start()
+                    .strip_prefix(',')
+                    .and_then(|rest| rest.split_once('('))
+                    .map(|(method, _)| method.trim())
+                    .filter(|method| METHODS.contains(method))
+                    .unwrap_or_else(|| panic!("{}: the route {} is not registered as `method(handler)` with one of {:?}", file, path, METHODS));
+                let path = path.split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(param) => format!("{{{}}}", param),
+                        None => segment.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (method.to_uppercase(), path)
+            })
+            .collect()
+    }
+
+    /// The `*_handlers.rs` files below `dir`, by their path relative to `src/handlers`.
+    fn handler_files(dir: &Path, relative: &str) -> Vec<String> {
+        let entries = std::fs::read_dir(dir)
+            .unwrap_or_else(|e| panic!("Could not list {}: {}", dir.display(), e));
+        let mut files = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = format!("{}{}", relative, name);
+            if entry.path().is_dir() {
+                files.extend(handler_files(&entry.path(), &format!("{}/", path)));
+            } else if name.ends_with("_handlers.rs") {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    fn documented_routes(openapi: &OpenApiDocument) -> BTreeSet<String> {
+        let mut routes = BTreeSet::new();
+        for (path, item) in &openapi.paths.paths {
+            let operations = [("GET", &item.get), ("PUT", &item.put), ("POST", &item.post), ("DELETE", &item.delete), ("PATCH", &item.patch)];
+            for (method, operation) in operations {
+                if operation.is_some() {
+                    routes.insert(format!("{} {}", method, path));
+                }
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn test_documents_every_route() {
+        let mut routes = BTreeSet::new();
+        for (file, source, prefixes) in ROUTE_SOURCES {
+            for (method, path) in registered_routes(file, source) {
+                for prefix in prefixes {
+                    routes.insert(format!("{} {}{}", method, prefix, path));
+                }
+            }
+        }
+        for route in UNDOCUMENTED {
+            assert!(routes.remove(route), "{} is no longer registered", route);
+        }
+
+        let documented = documented_routes(&openapi());
+        let undocumented: Vec<_> = routes.difference(&documented).collect();
+        let unregistered: Vec<_> = documented.difference(&routes).collect();
+        assert!(undocumented.is_empty(), "Routes missing from the OpenAPI document: {:?}", undocumented);
+        assert!(unregistered.is_empty(), "Documented routes that are not registered: {:?}", unregistered);
+    }
+
+    #[test]
+    fn test_scans_every_handler_module() {
+        let handlers = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/handlers");
+        let scanned: BTreeSet<_> = ROUTE_SOURCES.iter().map(|(file, _, _)| *file).chain(NOT_SCANNED).collect();
+
+        let missing: Vec<_> = handler_files(&handlers, "")
+            .into_iter()
+            .filter(|file| !scanned.contains(file.as_str()))
+            .collect();
+        assert!(missing.is_empty(), "Handler modules missing from ROUTE_SOURCES: {:?}", missing);
+    }
+
+    #[test]
+    fn test_registered_routes_span_lines() {
+        let source = ".route(\n    \"/medicines/:id/stock\",\n    post(add_stock),\n)";
+
+        assert_eq!(registered_routes("test.rs", source), [("POST".to_string(), "/medicines/{id}/stock".to_string())]);
+    }
+
+    #[test]
+    fn test_nested_routes_take_the_profile_id() {
+        let openapi = openapi();
+
+        let nested = &openapi.paths.paths["/v1/profiles/{pid}/medicines/{id}"];
+        let parameters = nested.parameters.as_ref().unwrap();
+        assert_eq!(parameters[0].name, "pid");
+        assert_eq!(nested.get.as_ref().unwrap().operation_id.as_deref(), Some("get_medicine_by_id_for_profile"));
+
+        let nested_v2 = &openapi.paths.paths["/v2/profiles/{pid}/schedules/daily/{date}"];
+        assert_eq!(nested_v2.parameters.as_ref().unwrap()[0].name, "pid");
+
+        let default_profile = &openapi.paths.paths["/v1/medicines/{id}"];
+        assert!(default_profile.parameters.is_none());
+        assert_eq!(default_profile.get.as_ref().unwrap().operation_id.as_deref(), Some("get_medicine_by_id"));
+    }
+
+    #[test]
+    fn test_unversioned_routes_are_deprecated() {
+        let openapi = openapi();
+        let operation = |path: &str| openapi.paths.paths[path].get.clone().unwrap();
+
+        let alias = operation("/profiles/{pid}/medicines/{id}");
+        assert!(matches!(alias.deprecated, Some(Deprecated::True)));
+        assert_eq!(alias.operation_id.as_deref(), Some("get_medicine_by_id_for_profile_unversioned"));
+        assert!(matches!(operation("/api-keys").deprecated, Some(Deprecated::True)));
+
+        assert!(operation("/v1/profiles/{pid}/medicines/{id}").deprecated.is_none());
+        assert!(operation("/v2/schedules/daily/{date}").deprecated.is_none());
+        assert!(operation("/health/ready").deprecated.is_none());
+    }
+
+    #[test]
+    fn test_documents_the_models() {
+        let openapi = openapi();
+        let schemas = &openapi.components.as_ref().unwrap().schemas;
+
+        for model in ["Medicine", "MedicineSchedule", "DailyScheduleWithDate", "DailyScheduleWithDateV2", "DosageHistory"] {
+            assert!(schemas.contains_key(model), "{} is not in the OpenAPI document", model);
+        }
+        assert!(openapi.to_json().is_ok());
+    }
+}
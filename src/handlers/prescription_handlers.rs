@@ -0,0 +1,201 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use std::sync::Arc;
+use crate::models::{Action, Prescription, ApiPrescription, RefillAlert, daily_usage};
+use super::{ApiError, AppState, IdPath, ProfileScope};
+
+pub fn prescription_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/prescriptions", post(create_prescription))
+        .route("/prescriptions", get(get_all_prescriptions))
+        .route("/prescriptions/refill-alerts", get(get_refill_alerts))
+        .route("/prescriptions/:id", get(get_prescription_by_id))
+        .route("/prescriptions/:id", put(update_prescription))
+        .route("/prescriptions/:id", delete(delete_prescription))
+}
+
+#[utoipa::path(
+    post,
+    path = "/prescriptions",
+    tag = "prescriptions",
+    request_body = ApiPrescription,
+    responses(
+        (status = 200, description = "The created prescription", body = Prescription),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
+async fn create_prescription(
+    scope: ProfileScope,
+    Json(api_prescription): Json<ApiPrescription>,
+) -> Result<Json<Prescription>, ApiError> {
+    tracing::info!("POST /prescriptions called");
+    
+    scope.require(Action::Manage)?;
+    
+    let repo = &scope.repos.prescription_repo;
+    // A prescription must be for a known medicine
+    scope.repos.medicine_repo.get_by_id(&api_prescription.medicine_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    
+    let id = repo.create(api_prescription).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let prescription = repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(prescription))
+}
+
+#[utoipa::path(
+    get,
+    path = "/prescriptions",
+    tag = "prescriptions",
+    responses(
+        (status = 200, description = "All prescriptions", body = Vec<Prescription>),
+    )
+)]
+async fn get_all_prescriptions(
+    scope: ProfileScope,
+) -> Result<Json<Vec<Prescription>>, StatusCode> {
+    tracing::info!("GET /prescriptions called");
+    
+    let repo = &scope.repos.prescription_repo;
+    let prescriptions = repo.get_all().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(prescriptions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/prescriptions/{id}",
+    tag = "prescriptions",
+    params(("id" = String, Path, description = "Id of the prescription")),
+    responses(
+        (status = 200, description = "The prescription", body = Prescription),
+        (status = 404, description = "No prescription with this id"),
+    )
+)]
+async fn get_prescription_by_id(
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<Prescription>, StatusCode> {
+    tracing::info!("GET /prescriptions/{}", id);
+    
+    let repo = &scope.repos.prescription_repo;
+    let prescription = repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    Ok(Json(prescription))
+}
+
+#[utoipa::path(
+    put,
+    path = "/prescriptions/{id}",
+    tag = "prescriptions",
+    params(("id" = String, Path, description = "Id of the prescription")),
+    request_body = ApiPrescription,
+    responses(
+        (status = 200, description = "The updated prescription", body = Prescription),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No prescription with this id"),
+    )
+)]
+async fn update_prescription(
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Json(api_prescription): Json<ApiPrescription>,
+) -> Result<Json<Prescription>, ApiError> {
+    tracing::info!("PUT /prescriptions/{}", id);
+    
+    scope.require(Action::Manage)?;
+    
+    let repo = &scope.repos.prescription_repo;
+    // Check if prescription exists
+    repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    repo.update(&id, api_prescription).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let prescription = repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(prescription))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/prescriptions/{id}",
+    tag = "prescriptions",
+    params(("id" = String, Path, description = "Id of the prescription")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No prescription with this id"),
+    )
+)]
+async fn delete_prescription(
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<StatusCode, ApiError> {
+    tracing::info!("DELETE /prescriptions/{}", id);
+    
+    scope.require(Action::Manage)?;
+    
+    let repo = &scope.repos.prescription_repo;
+    // Check if prescription exists
+    repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    repo.delete(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/prescriptions/refill-alerts",
+    tag = "prescriptions",
+    responses(
+        (status = 200, description = "Prescriptions to refill or renew soon", body = Vec<RefillAlert>),
+    )
+)]
+async fn get_refill_alerts(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<Json<Vec<RefillAlert>>, StatusCode> {
+    tracing::info!("GET /prescriptions/refill-alerts called");
+    
+    let prescriptions = scope.repos.prescription_repo.get_all().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let schedules = scope.repos.schedule_repo.get_all().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let medicine_ids: Vec<_> = prescriptions.iter().map(|prescription| prescription.medicine_id.clone()).collect();
+    let medicines = scope.repos.medicine_repo.get_many(&medicine_ids).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let today = state.today();
+    
+    let mut alerts = Vec::new();
+    for prescription in prescriptions {
+        if let Some(medicine) = medicines.get(&prescription.medicine_id) {
+            let usage = daily_usage(&schedules, &medicine.id);
+            alerts.extend(prescription.refill_alert(medicine, usage, today));
+        }
+    }
+    alerts.sort_by_key(|alert| alert.stock_runout_date);
+    
+    Ok(Json(alerts))
+}
@@ -0,0 +1,168 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use std::sync::Arc;
+use crate::auth::AuthenticatedUser;
+use crate::models::{Action, ApiProfile, Grant, Profile, Role};
+use super::{ApiError, AppState, ProfileScope};
+
+pub fn profile_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/profiles", post(create_profile))
+        .route("/profiles", get(get_all_profiles))
+        .route("/profiles/:pid", get(get_profile_by_id))
+        .route("/profiles/:pid", put(update_profile))
+        .route("/profiles/:pid", delete(delete_profile))
+}
+
+#[utoipa::path(
+    post,
+    path = "/profiles",
+    tag = "profiles",
+    request_body = ApiProfile,
+    responses(
+        (status = 200, description = "The created profile, the user is its patient", body = Profile),
+    )
+)]
+async fn create_profile(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(api_profile): Json<ApiProfile>,
+) -> Result<Json<Profile>, StatusCode> {
+    tracing::info!("POST /profiles called");
+    
+    let repo = &state.profile_repo;
+    let id = repo.create(api_profile).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    // The user creating a profile is its patient
+    let grant = Grant { profile_id: id.clone(), subject: user.subject, role: Role::Patient };
+    state.grant_repo.save(&grant).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let profile = repo.get_by_id(&id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(profile))
+}
+
+#[utoipa::path(
+    get,
+    path = "/profiles",
+    tag = "profiles",
+    responses(
+        (status = 200, description = "The profiles the user has access to", body = Vec<Profile>),
+    )
+)]
+async fn get_all_profiles(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<Profile>>, StatusCode> {
+    tracing::info!("GET /profiles called");
+    
+    let repo = &state.profile_repo;
+    let mut profiles = Vec::new();
+    // Only the profiles the user has been granted access to
+    for profile in repo.get_all().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        let role = state.role_of(&profile.id, &user.subject).await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if role.is_some() {
+            profiles.push(profile);
+        }
+    }
+    
+    Ok(Json(profiles))
+}
+
+#[utoipa::path(
+    get,
+    path = "/profiles/{pid}",
+    tag = "profiles",
+    params(("pid" = String, Path, description = "Id of the profile")),
+    responses(
+        (status = 200, description = "The profile", body = Profile),
+        (status = 403, description = "No access to this profile"),
+        (status = 404, description = "No profile with this id"),
+    )
+)]
+async fn get_profile_by_id(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<Json<Profile>, StatusCode> {
+    tracing::info!("GET /profiles/{}", scope.profile_id);
+    
+    let repo = &state.profile_repo;
+    let profile = repo.get_by_id(&scope.profile_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    Ok(Json(profile))
+}
+
+#[utoipa::path(
+    put,
+    path = "/profiles/{pid}",
+    tag = "profiles",
+    params(("pid" = String, Path, description = "Id of the profile")),
+    request_body = ApiProfile,
+    responses(
+        (status = 200, description = "The updated profile", body = Profile),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No profile with this id"),
+    )
+)]
+async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Json(api_profile): Json<ApiProfile>,
+) -> Result<Json<Profile>, ApiError> {
+    tracing::info!("PUT /profiles/{}", scope.profile_id);
+    
+    scope.require(Action::Manage)?;
+    
+    let repo = &state.profile_repo;
+    repo.update(&scope.profile_id, api_profile).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let profile = repo.get_by_id(&scope.profile_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(profile))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/profiles/{pid}",
+    tag = "profiles",
+    params(("pid" = String, Path, description = "Id of the profile")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No profile with this id"),
+    )
+)]
+async fn delete_profile(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<StatusCode, ApiError> {
+    tracing::info!("DELETE /profiles/{}", scope.profile_id);
+    
+    scope.require(Action::Manage)?;
+    
+    // Deletes everything stored for the profile as well
+    let repo = &state.profile_repo;
+    repo.delete(&scope.profile_id, &state.profile_prefix(&scope.profile_id)).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.grant_repo.delete_by_profile(&scope.profile_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.calendar_repo.delete(&scope.profile_id, &scope.auditor).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,15 +1,27 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::Json,
+    response::{Json, Response},
     routing::{delete, get, post, put},
     Router,
 };
+use chrono::Utc;
+use serde::Deserialize;
 use std::sync::Arc;
-use crate::models::{MedicineSchedule, ApiMedicineSchedule, DailyScheduleWithDate};
-use crate::repositories::{MedicineRepository, MedicineScheduleRepository};
+use crate::models::{
+    parse_ics_events, Action, MedicineSchedule, ApiMedicineSchedule, CalendarSubscription, DailyScheduleWithDate, IcsImportQuery,
+    IcsImportReport, NewCalendarSubscription, ScheduleRangeQuery, WithWarnings,
+};
+use super::{ApiError, AppState, IdPath, ProfileScope};
+use super::calendar_handlers::calendar_response;
+use super::interaction_handlers::current_regimen;
+
+#[derive(Debug, Deserialize)]
+struct DatePath {
+    date: String,
+}
 
-pub fn schedule_routes() -> Router<Arc<(MedicineRepository, MedicineScheduleRepository)>> {
+pub fn schedule_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/schedules", post(create_schedule))
         .route("/schedules", get(get_all_schedules))
@@ -17,15 +29,39 @@ pub fn schedule_routes() -> Router<Arc<(MedicineRepository, MedicineScheduleRepo
         .route("/schedules/:id", put(update_schedule))
         .route("/schedules/:id", delete(delete_schedule))
         .route("/schedules/daily/:date", get(get_daily_schedule))
+        .route("/schedules/range", get(get_schedule_range))
+        .route("/schedules/calendar.ics", get(get_schedule_calendar))
+        .route("/schedules/calendar/subscription", post(create_calendar_subscription))
+        .route("/schedules/calendar/subscription", delete(delete_calendar_subscription))
+        .route("/schedules/import/ics", post(import_ics))
 }
 
+#[utoipa::path(
+    post,
+    path = "/schedules",
+    tag = "schedules",
+    request_body = ApiMedicineSchedule,
+    responses(
+        (status = 200, description = "The created schedule, with warnings about interactions with the current regimen", body = WithWarnings<MedicineSchedule>),
+        (status = 400, description = "The medicine does not exist"),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
 async fn create_schedule(
-    State(repos): State<Arc<(MedicineRepository, MedicineScheduleRepository)>>,
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
     Json(api_schedule): Json<ApiMedicineSchedule>,
-) -> Result<Json<MedicineSchedule>, StatusCode> {
+) -> Result<Json<WithWarnings<MedicineSchedule>>, ApiError> {
     tracing::info!("POST /schedules called");
     
-    let (_, schedule_repo) = &*repos;
+    scope.require(Action::Manage)?;
+    
+    let schedule_repo = &scope.repos.schedule_repo;
+    // The medicine must belong to the same profile
+    let medicine = scope.repos.medicine_repo.get_by_id(&api_schedule.medicine_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    
     let id = schedule_repo.create(api_schedule).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
@@ -33,28 +69,54 @@ async fn create_schedule(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
     
-    Ok(Json(schedule))
+    // Warn about interactions between the scheduled medicine and the rest of the regimen
+    let regimen = current_regimen(&scope.repos).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let warnings = state.interactions.check(&medicine, &regimen)
+        .iter()
+        .map(|finding| finding.to_warning())
+        .collect();
+    
+    Ok(Json(WithWarnings::new(schedule, warnings)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/schedules",
+    tag = "schedules",
+    responses(
+        (status = 200, description = "All schedules", body = Vec<MedicineSchedule>),
+    )
+)]
 async fn get_all_schedules(
-    State(repos): State<Arc<(MedicineRepository, MedicineScheduleRepository)>>,
+    scope: ProfileScope,
 ) -> Result<Json<Vec<MedicineSchedule>>, StatusCode> {
     tracing::info!("GET /schedules called");
     
-    let (_, schedule_repo) = &*repos;
+    let schedule_repo = &scope.repos.schedule_repo;
     let schedules = schedule_repo.get_all().await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(schedules))
 }
 
+#[utoipa::path(
+    get,
+    path = "/schedules/{id}",
+    tag = "schedules",
+    params(("id" = String, Path, description = "Id of the schedule")),
+    responses(
+        (status = 200, description = "The schedule", body = MedicineSchedule),
+        (status = 404, description = "No schedule with this id"),
+    )
+)]
 async fn get_schedule_by_id(
-    State(repos): State<Arc<(MedicineRepository, MedicineScheduleRepository)>>,
-    Path(id): Path<String>,
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<MedicineSchedule>, StatusCode> {
     tracing::info!("GET /schedules/{}", id);
     
-    let (_, schedule_repo) = &*repos;
+    let schedule_repo = &scope.repos.schedule_repo;
     let schedule = schedule_repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -62,19 +124,39 @@ async fn get_schedule_by_id(
     Ok(Json(schedule))
 }
 
+#[utoipa::path(
+    put,
+    path = "/schedules/{id}",
+    tag = "schedules",
+    params(("id" = String, Path, description = "Id of the schedule")),
+    request_body = ApiMedicineSchedule,
+    responses(
+        (status = 200, description = "The updated schedule", body = MedicineSchedule),
+        (status = 400, description = "The medicine does not exist"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No schedule with this id"),
+    )
+)]
 async fn update_schedule(
-    State(repos): State<Arc<(MedicineRepository, MedicineScheduleRepository)>>,
-    Path(id): Path<String>,
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
     Json(api_schedule): Json<ApiMedicineSchedule>,
-) -> Result<Json<MedicineSchedule>, StatusCode> {
+) -> Result<Json<MedicineSchedule>, ApiError> {
     tracing::info!("PUT /schedules/{}", id);
     
-    let (_, schedule_repo) = &*repos;
+    scope.require(Action::Manage)?;
+    
+    let schedule_repo = &scope.repos.schedule_repo;
     // Check if schedule exists
     schedule_repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
     
+    // The medicine must belong to the same profile
+    scope.repos.medicine_repo.get_by_id(&api_schedule.medicine_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    
     schedule_repo.update(&id, api_schedule).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
@@ -85,13 +167,26 @@ async fn update_schedule(
     Ok(Json(schedule))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/schedules/{id}",
+    tag = "schedules",
+    params(("id" = String, Path, description = "Id of the schedule")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "The role on the profile does not allow this"),
+        (status = 404, description = "No schedule with this id"),
+    )
+)]
 async fn delete_schedule(
-    State(repos): State<Arc<(MedicineRepository, MedicineScheduleRepository)>>,
-    Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+    scope: ProfileScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<StatusCode, ApiError> {
     tracing::info!("DELETE /schedules/{}", id);
     
-    let (_, schedule_repo) = &*repos;
+    scope.require(Action::Manage)?;
+    
+    let schedule_repo = &scope.repos.schedule_repo;
     // Check if schedule exists
     schedule_repo.get_by_id(&id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
@@ -103,15 +198,276 @@ async fn delete_schedule(
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    get,
+    path = "/schedules/daily/{date}",
+    tag = "schedules",
+    params(("date" = String, Path, description = "Date as YYYY-MM-DD")),
+    responses(
+        (status = 200, description = "The doses to take on the date, by time of day", body = DailyScheduleWithDate),
+    )
+)]
 async fn get_daily_schedule(
-    State(repos): State<Arc<(MedicineRepository, MedicineScheduleRepository)>>,
-    Path(date): Path<String>,
+    scope: ProfileScope,
+    Path(DatePath { date }): Path<DatePath>,
 ) -> Result<Json<DailyScheduleWithDate>, StatusCode> {
     tracing::info!("GET /schedules/daily/{}", date);
     
-    let (medicine_repo, schedule_repo) = &*repos;
-    let daily_schedule = schedule_repo.get_daily_schedule_with_date(&date, medicine_repo).await
+    let schedule_repo = &scope.repos.schedule_repo;
+    let daily_schedule = schedule_repo.get_daily_schedule_with_date(&date, &scope.repos.medicine_repo).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(daily_schedule))
-} 
\ No newline at end of file
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules/range",
+    tag = "schedules",
+    params(ScheduleRangeQuery),
+    responses(
+        (status = 200, description = "The daily schedule of every date in the range, with whether each time was taken", body = Vec<DailyScheduleWithDate>),
+        (status = 400, description = "Invalid dates, or a range of more than 92 days"),
+    )
+)]
+async fn get_schedule_range(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Query(range): Query<ScheduleRangeQuery>,
+) -> Result<Json<Vec<DailyScheduleWithDate>>, StatusCode> {
+    tracing::info!("GET /schedules/range from {} to {}", range.from, range.to);
+    
+    if !range.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let repos = &scope.repos;
+    let schedules = repos.schedule_repo.get_schedule_range(range.from, range.to, state.timezone, &repos.medicine_repo, &repos.dosage_history_repo).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(schedules.into_iter().map(DailyScheduleWithDate::from).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules/calendar.ics",
+    tag = "schedules",
+    responses(
+        (status = 200, description = "The schedules as events repeating daily from today, with a reminder when a dose is due", body = String, content_type = "text/calendar"),
+    )
+)]
+async fn get_schedule_calendar(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<Response, StatusCode> {
+    tracing::info!("GET /schedules/calendar.ics called");
+    
+    calendar_response(&scope.repos, state.today()).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/schedules/calendar/subscription",
+    tag = "schedules",
+    responses(
+        (status = 200, description = "The path of the calendar feed, which calendar apps can fetch without logging in. A previous subscription stops working", body = NewCalendarSubscription),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
+async fn create_calendar_subscription(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<Json<NewCalendarSubscription>, ApiError> {
+    tracing::info!("POST /schedules/calendar/subscription called");
+    
+    scope.require(Action::Manage)?;
+    
+    let (subscription, token) = CalendarSubscription::generate(scope.profile_id, scope.user.subject, Utc::now());
+    state.calendar_repo.save(&subscription, &scope.auditor).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(NewCalendarSubscription {
+        path: CalendarSubscription::feed_path(&token),
+        created_at: subscription.created_at,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/schedules/calendar/subscription",
+    tag = "schedules",
+    responses(
+        (status = 204, description = "The calendar feed of the profile no longer works"),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
+async fn delete_calendar_subscription(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+) -> Result<StatusCode, ApiError> {
+    tracing::info!("DELETE /schedules/calendar/subscription called");
+    
+    scope.require(Action::Manage)?;
+    
+    state.calendar_repo.delete(&scope.profile_id, &scope.auditor).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/schedules/import/ics",
+    tag = "schedules",
+    params(IcsImportQuery),
+    request_body(content = String, content_type = "text/calendar", description = "An iCalendar file, each daily event taking a medicine becomes a schedule"),
+    responses(
+        (status = 200, description = "The schedules the events create, the events left out and the interactions the schedules add to the regimen. Nothing is created unless `commit` is set, then all schedules are created or none", body = IcsImportReport),
+        (status = 400, description = "Not an iCalendar file"),
+        (status = 403, description = "The role on the profile does not allow this"),
+    )
+)]
+async fn import_ics(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Query(query): Query<IcsImportQuery>,
+    ics: String,
+) -> Result<Json<IcsImportReport>, ApiError> {
+    tracing::info!("POST /schedules/import/ics called, commit: {}", query.commit);
+    
+    scope.require(Action::Manage)?;
+    
+    let events = parse_ics_events(&ics).ok_or(StatusCode::BAD_REQUEST)?;
+    let repos = &scope.repos;
+    let medicines = repos.medicine_repo.get_all().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let existing = repos.schedule_repo.get_all().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let mut report = IcsImportReport::plan(&events, &medicines, &existing, state.timezone);
+    
+    // Warn about interactions of the medicines the import adds to the regimen, as
+    // when creating a single schedule
+    let mut regimen = current_regimen(repos).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for schedule in &report.created {
+        let Some(medicine) = medicines.iter().find(|medicine| medicine.id == schedule.medicine_id) else {
+            continue;
+        };
+        if regimen.iter().all(|other| other.id != medicine.id) {
+            report.warnings.extend(state.interactions.check(medicine, &regimen).iter().map(|finding| finding.to_warning()));
+            regimen.push(medicine.clone());
+        }
+    }
+    
+    if query.commit {
+        let api_schedules: Vec<_> = report.created.iter().map(|schedule| schedule.to_api_schedule()).collect();
+        let ids = repos.schedule_repo.create_all(&api_schedules).await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        for (schedule, id) in report.created.iter_mut().zip(ids) {
+            schedule.schedule_id = Some(id);
+        }
+        report.committed = true;
+    }
+    
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::{to_bytes, Body}, http::{header, Request}};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use std::time::Duration;
+    use tower::Service;
+    use crate::auth::Authenticator;
+    use crate::handlers::routes;
+    use crate::models::{ApiMedicine, InteractionTable, DEFAULT_PROFILE_ID};
+    use crate::repositories::{
+        ApiKeyRepository, CalendarSubscriptionRepository, GrantRepository, ProfileRepository, RedisConnection, Repositories,
+    };
+
+    const SECRET: &str = "test-secret";
+
+    fn create_test_state() -> Arc<AppState> {
+        let redis = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+        Arc::new(AppState {
+            redis: redis.clone(),
+            namespace: "test:import:".to_string(),
+            authenticator: Authenticator::new(Some(SECRET), None).unwrap(),
+            profile_repo: ProfileRepository::new(redis.clone(), "test:import:profile:".to_string()),
+            grant_repo: GrantRepository::new(redis.clone(), "test:import:grant:".to_string()),
+            api_key_repo: ApiKeyRepository::new(redis.clone(), "test:import:apikey:".to_string()),
+            calendar_repo: CalendarSubscriptionRepository::new(redis, "test:import:calendar:".to_string()),
+            interactions: InteractionTable::default(),
+            default_profile_owner: Some("user-1".to_string()),
+            timezone: chrono_tz::Tz::UTC,
+            expiring_within_days: 30,
+            reorder_within_days: 7,
+            metrics: metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder().handle(),
+            shutdown: tokio_util::sync::CancellationToken::new(),
+            workers: Default::default(),
+        })
+    }
+
+    async fn import(state: &Arc<AppState>, uri: &str, ics: &str) -> (StatusCode, serde_json::Value) {
+        let claims = serde_json::json!({ "sub": "user-1", "exp": Utc::now().timestamp() + 3600 });
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET.as_bytes())).unwrap();
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "text/calendar")
+            .body(Body::from(ics.to_string()))
+            .unwrap();
+        
+        let response = routes(state.clone()).with_state(state.clone()).call(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_import_ics_dry_run_and_commit() {
+        let state = create_test_state();
+        let repos = Repositories::new(&state.redis, &state.profile_prefix(DEFAULT_PROFILE_ID));
+        for schedule in repos.schedule_repo.get_all().await.unwrap() {
+            repos.schedule_repo.delete(&schedule.id).await.unwrap();
+        }
+        let medicine_id = repos.medicine_repo.create(ApiMedicine {
+            name: "Paracetamol".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 20.0,
+            prn: None,
+            active_ingredients: vec![],
+        }).await.unwrap();
+        let ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n\
+            BEGIN:VEVENT\r\nSUMMARY:Take 2 × Paracetamol\r\nDTSTART:20240115T080000\r\nRRULE:FREQ=DAILY\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nSUMMARY:Ibuprofen\r\nDTSTART:20240115T080000\r\nRRULE:FREQ=DAILY\r\nEND:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        
+        let (status, report) = import(&state, "/v1/schedules/import/ics", ics).await;
+        
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["committed"], false);
+        assert_eq!(report["created"].as_array().unwrap().len(), 1);
+        assert_eq!(report["unmatched"][0]["reason"], "unknown_medicine");
+        assert!(repos.schedule_repo.get_all().await.unwrap().is_empty());
+        
+        let (status, report) = import(&state, "/v1/schedules/import/ics?commit=true", ics).await;
+        
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["committed"], true);
+        let schedules = repos.schedule_repo.get_all().await.unwrap();
+        assert_eq!(schedules.len(), 1);
+        assert_eq!(report["created"][0]["schedule_id"], schedules[0].id.as_str());
+        assert_eq!((schedules[0].time.as_str(), schedules[0].medicine_id.as_str(), schedules[0].amount), ("08:00", medicine_id.as_str(), 2.0));
+        
+        let (status, _) = import(&state, "/v1/schedules/import/ics", "not a calendar").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        
+        repos.schedule_repo.delete(&schedules[0].id).await.unwrap();
+        repos.medicine_repo.delete(&medicine_id).await.unwrap();
+    }
+}
@@ -7,26 +7,30 @@ use serde_json::json;
 use std::sync::Arc;
 use tower::ServiceExt;
 
-use crate::models::{ApiMedicine, ApiMedicineSchedule, ApiDosageHistory};
-use crate::repositories::{MedicineRepository, MedicineScheduleRepository, DosageHistoryRepository};
+use crate::models::{ApiMedicine, ApiMedicineSchedule, ApiDosageHistory, InteractionTable};
+use crate::auth::Authenticator;
+use crate::repositories::{ApiKeyRepository, CalendarSubscriptionRepository, GrantRepository, ProfileRepository, RedisConnection};
+use super::AppState;
 
-pub async fn create_test_medicine_repo() -> Arc<MedicineRepository> {
-    Arc::new(
-        MedicineRepository::new("redis://localhost:6379", "test:medicine:".to_string()).unwrap()
-    )
-}
-
-pub async fn create_test_schedule_repos() -> Arc<(MedicineRepository, MedicineScheduleRepository)> {
-    Arc::new((
-        MedicineRepository::new("redis://localhost:6379", "test:medicine:".to_string()).unwrap(),
-        MedicineScheduleRepository::new("redis://localhost:6379", "test:schedule:".to_string()).unwrap()
-    ))
-}
-
-pub async fn create_test_dosage_history_repo() -> Arc<DosageHistoryRepository> {
-    Arc::new(
-        DosageHistoryRepository::new("redis://localhost:6379", "test:dosage:".to_string()).unwrap()
-    )
+pub async fn create_test_state() -> Arc<AppState> {
+    let redis = RedisConnection::new("redis://localhost:6379", std::time::Duration::from_secs(1), std::time::Duration::from_secs(1)).unwrap();
+    Arc::new(AppState {
+        redis: redis.clone(),
+        namespace: "test:".to_string(),
+        authenticator: Authenticator::new(Some("test-secret"), None).unwrap(),
+        profile_repo: ProfileRepository::new(redis.clone(), "test:profile:".to_string()),
+        grant_repo: GrantRepository::new(redis.clone(), "test:grant:".to_string()),
+        api_key_repo: ApiKeyRepository::new(redis.clone(), "test:apikey:".to_string()),
+        calendar_repo: CalendarSubscriptionRepository::new(redis.clone(), "test:calendar:".to_string()),
+        interactions: InteractionTable::default(),
+        default_profile_owner: Some("test-user".to_string()),
+        timezone: chrono_tz::Tz::UTC,
+        expiring_within_days: 30,
+        reorder_within_days: 7,
+        metrics: metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder().handle(),
+        shutdown: tokio_util::sync::CancellationToken::new(),
+        workers: Default::default(),
+    })
 }
 
 pub async fn make_request<B>(app: axum::Router, method: &str, uri: &str, body: Option<B>) -> Response
@@ -53,6 +57,8 @@ pub fn create_test_api_medicine() -> ApiMedicine {
         dose: 500.0,
         unit: "mg".to_string(),
         stock: 100.0,
+        prn: None,
+        active_ingredients: vec![],
     }
 }
 
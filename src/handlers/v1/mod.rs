@@ -0,0 +1,51 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+    Router,
+};
+use std::sync::Arc;
+use super::{
+    api_key_handlers, audit_handlers, dosage_history_handlers, grant_handlers, interaction_handlers, medicine_handlers,
+    prescription_handlers, profile_handlers, schedule_handlers, AppState,
+};
+
+/// Header marking a response as coming from a deprecated route.
+pub const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+
+/// Version 1 of the API, served under `/v1` and, deprecated, without a prefix.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .merge(profile_scoped_routes())
+        .nest("/profiles/:pid", profile_scoped_routes())
+        .merge(profile_handlers::profile_routes())
+        .merge(api_key_handlers::api_key_routes())
+}
+
+/// The routes that work on the data of a single profile. They are served both for
+/// the default profile and under `/profiles/:pid`.
+pub fn profile_scoped_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .merge(medicine_handlers::medicine_routes())
+        .merge(schedule_handlers::schedule_routes())
+        .merge(dosage_history_handlers::dosage_history_routes())
+        .merge(prescription_handlers::prescription_routes())
+        .merge(interaction_handlers::interaction_routes())
+        .merge(grant_handlers::grant_routes())
+        .merge(audit_handlers::audit_routes())
+}
+
+/// Marks a response of an unprefixed route as deprecated, linking to the same route
+/// under `/v1`.
+pub async fn deprecated_alias(request: Request, next: Next) -> Response {
+    let successor = format!("</v1{}>; rel=\"successor-version\"", request.uri().path());
+    let mut response = next.run(request).await;
+    
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
@@ -0,0 +1,19 @@
+use axum::Router;
+use std::sync::Arc;
+use super::AppState;
+
+pub mod schedule_handlers;
+
+/// Version 2 of the API, served under `/v2`. It only holds the routes whose models
+/// changed since version 1.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .merge(profile_scoped_routes())
+        .nest("/profiles/:pid", profile_scoped_routes())
+}
+
+/// The version 2 routes that work on the data of a single profile.
+pub fn profile_scoped_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .merge(schedule_handlers::schedule_routes())
+}
@@ -0,0 +1,80 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::models::{DailyScheduleWithDateV2, ScheduleRangeQuery};
+use crate::handlers::{AppState, ProfileScope};
+
+#[derive(Debug, Deserialize)]
+struct DatePath {
+    date: String,
+}
+
+pub fn schedule_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/schedules/daily/:date", get(get_daily_schedule))
+        .route("/schedules/range", get(get_schedule_range))
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules/daily/{date}",
+    tag = "schedules",
+    operation_id = "get_daily_schedule_v2",
+    params(("date" = NaiveDate, Path, description = "Date as YYYY-MM-DD")),
+    responses(
+        (status = 200, description = "The doses to take on the date by time of day, and whether they were taken", body = DailyScheduleWithDateV2),
+        (status = 400, description = "Invalid date"),
+    )
+)]
+async fn get_daily_schedule(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Path(DatePath { date }): Path<DatePath>,
+) -> Result<Json<DailyScheduleWithDateV2>, StatusCode> {
+    tracing::info!("GET /v2/schedules/daily/{}", date);
+    
+    let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    
+    let repos = &scope.repos;
+    let daily_schedule = repos.schedule_repo.get_daily_schedule_v2(date, state.timezone, &repos.medicine_repo, &repos.dosage_history_repo).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(daily_schedule))
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules/range",
+    tag = "schedules",
+    operation_id = "get_schedule_range_v2",
+    params(ScheduleRangeQuery),
+    responses(
+        (status = 200, description = "The daily schedule of every date in the range, with whether each dose was taken", body = Vec<DailyScheduleWithDateV2>),
+        (status = 400, description = "Invalid dates, or a range of more than 92 days"),
+    )
+)]
+async fn get_schedule_range(
+    State(state): State<Arc<AppState>>,
+    scope: ProfileScope,
+    Query(range): Query<ScheduleRangeQuery>,
+) -> Result<Json<Vec<DailyScheduleWithDateV2>>, StatusCode> {
+    tracing::info!("GET /v2/schedules/range from {} to {}", range.from, range.to);
+    
+    if !range.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let repos = &scope.repos;
+    let schedules = repos.schedule_repo.get_schedule_range(range.from, range.to, state.timezone, &repos.medicine_repo, &repos.dosage_history_repo).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(schedules))
+}
@@ -1,79 +1,219 @@
+mod auth;
 mod config;
 mod handlers;
 mod models;
 mod repositories;
+mod telemetry;
 
-use axum::{
-    http::{Method, StatusCode},
-    response::IntoResponse,
-    routing::get,
-    Router,
-};
+use axum::http::{header, HeaderValue, Method};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceBuilder;
+use tower_http::{
+    cors::{Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 
+use auth::Authenticator;
 use config::Config;
-use handlers::{medicine_handlers, schedule_handlers, dosage_history_handlers};
-use repositories::{MedicineRepository, MedicineScheduleRepository, DosageHistoryRepository};
+use handlers::{v1::DEPRECATION_HEADER, AppState, Workers};
+use models::InteractionTable;
+use telemetry::REQUEST_ID_HEADER;
+use repositories::{ApiKeyRepository, CalendarSubscriptionRepository, GrantRepository, ProfileRepository, RedisConnection};
+
+/// How long readiness reports shutting down before connections are no longer accepted.
+const NOT_READY_PERIOD: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load environment variables
     dotenv::dotenv().ok();
 
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Load the config file, environment variables and command line flags
+    let config = Config::load()?;
+
+    // Initialize tracing, keeping the guard to flush exported spans on exit
+    let _tracing = telemetry::init_tracing(&config)?;
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::info!("Exporting traces of {} to {}", config.service_name, endpoint);
+    }
+
+    // Used both for serving HTTPS and for TLS connections to Redis
+    rustls::crypto::ring::default_provider().install_default()
+        .map_err(|_| anyhow::anyhow!("Could not install the TLS crypto provider"))?;
 
-    let config = Config::from_env();
     tracing::info!("Server running on port: {}", config.server_port);
-    tracing::info!("Redis connection: {}:{}", config.redis_host, config.redis_port);
-
-    // Initialize repositories
-    let medicine_repo = Arc::new(
-        MedicineRepository::new(&config.redis_url(), "prod:medicine:".to_string())?
-    );
-    let _schedule_repo = Arc::new(
-        MedicineScheduleRepository::new(&config.redis_url(), "prod:schedule:".to_string())?
-    );
-    let dosage_history_repo = Arc::new(
-        DosageHistoryRepository::new(&config.redis_url(), "prod:dosage:".to_string())?
-    );
-
-    // Create a tuple of repositories for schedule routes
-    let schedule_repos = Arc::new((
-        MedicineRepository::new(&config.redis_url(), "prod:medicine:".to_string())?,
-        MedicineScheduleRepository::new(&config.redis_url(), "prod:schedule:".to_string())?
-    ));
+    tracing::info!("Redis connection: {}", config.redacted_redis_url());
+
+    // Load the drug interaction table
+    let interactions = match &config.interactions_file {
+        Some(file) => InteractionTable::load(std::path::Path::new(file))?,
+        None => InteractionTable::default(),
+    };
+    if interactions.is_empty() {
+        tracing::warn!("No drug interactions loaded, only duplicate ingredients are checked");
+    } else {
+        tracing::info!("Loaded {} drug interactions", interactions.len());
+    }
+
+    // Configure authentication, tokens are signed with the shared secret (HS256) or a key from the JWKS file (RS256).
+    // Devices and integrations use API keys instead
+    let jwks = config.jwt_jwks_file.as_ref()
+        .map(|file| Authenticator::load_jwks(std::path::Path::new(file)))
+        .transpose()?;
+    let authenticator = Authenticator::new(config.jwt_secret.as_deref(), jwks)?;
+
+    // Record metrics, histograms get buckets from 1ms up to 10s
+    let metrics = PrometheusBuilder::new()
+        .set_buckets(&[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0])?
+        .install_recorder()?;
+
+    // Background workers stop once shutdown starts, which is awaited before exiting
+    let shutdown = CancellationToken::new();
+    let workers = Workers::default();
+    workers.spawn(metrics_upkeep(metrics.clone(), shutdown.clone()));
+
+    // Initialize repositories, the data of each profile lives under its own key prefix.
+    // All of them share one multiplexed Redis connection
+    let redis = RedisConnection::new(&config.redis_url, config.redis_connection_timeout, config.redis_response_timeout)?;
+    let state = Arc::new(AppState {
+        redis: redis.clone(),
+        namespace: config.namespace.clone(),
+        authenticator,
+        profile_repo: ProfileRepository::new(redis.clone(), format!("{}profile:", config.namespace)),
+        grant_repo: GrantRepository::new(redis.clone(), format!("{}grant:", config.namespace)),
+        api_key_repo: ApiKeyRepository::new(redis.clone(), format!("{}apikey:", config.namespace)),
+        calendar_repo: CalendarSubscriptionRepository::new(redis.clone(), format!("{}calendar:", config.namespace)),
+        interactions,
+        default_profile_owner: config.default_profile_owner.clone(),
+        timezone: config.timezone,
+        expiring_within_days: config.expiring_within_days,
+        reorder_within_days: config.reorder_within_days,
+        metrics,
+        shutdown: shutdown.clone(),
+        workers: workers.clone(),
+    });
+
+    workers.spawn(handlers::metrics_handlers::domain_metrics_worker(state.clone()));
 
     // Configure CORS
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_origin(Any)
-        .allow_headers(Any);
+    let cors = cors_layer(&config)?;
 
     // Build application with routes
-    let app = Router::new()
-        .merge(medicine_handlers::medicine_routes().with_state(medicine_repo))
-        .merge(schedule_handlers::schedule_routes().with_state(schedule_repos))
-        .merge(dosage_history_handlers::dosage_history_routes().with_state(dosage_history_repo))
-        .route("/health", get(health_check))
-        .layer(cors);
+    let app = handlers::routes(state.clone())
+        .with_state(state)
+        .layer(cors)
+        // Every request gets an id, unless the client sent one, which is logged with
+        // all of its events and returned in the response
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+                .layer(TraceLayer::new_for_http()
+                    .make_span_with(telemetry::request_span)
+                    .on_response(telemetry::record_response))
+                .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER)),
+        );
 
-    // Run server
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.server_port)).await?;
-    tracing::info!("Listening on http://0.0.0.0:{}", config.server_port);
+    // Run server, over HTTPS when a certificate is configured, until SIGINT or SIGTERM
+    let address = SocketAddr::new(config.bind_address.parse()?, config.server_port);
+    let handle = Handle::new();
+    let signal = tokio::spawn(shutdown_on_signal(handle.clone(), shutdown.clone(), config.shutdown_timeout));
+    match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let tls = RustlsConfig::from_pem_file(cert_file, key_file).await?;
+            tracing::info!("Listening on https://{}", address);
 
-    axum::serve(listener, app).await?;
+            axum_server::bind_rustls(address, tls).handle(handle).serve(app.into_make_service()).await?;
+        }
+        (None, None) => {
+            tracing::info!("Listening on http://{}", address);
+
+            axum_server::bind(address).handle(handle).serve(app.into_make_service()).await?;
+        }
+        _ => return Err(anyhow::anyhow!("TLS_CERT_FILE and TLS_KEY_FILE must be set together")),
+    }
+
+    // All requests have finished, and with them their writes. The workers get what
+    // is left of the shutdown timeout
+    let deadline = signal.await?;
+    if tokio::time::timeout_at(deadline, workers.wait()).await.is_err() {
+        tracing::warn!("Background workers did not stop within {:?}", config.shutdown_timeout);
+    }
+    tracing::info!("Shut down");
 
     Ok(())
 }
 
-async fn health_check() -> impl IntoResponse {
-    StatusCode::OK
+/// Waits for SIGINT or SIGTERM, then reports not ready for `NOT_READY_PERIOD` so load
+/// balancers stop sending requests, before it stops accepting connections and gives the
+/// requests in flight the rest of `timeout` to finish. Returns when the timeout ends.
+async fn shutdown_on_signal(handle: Handle, shutdown: CancellationToken, timeout: Duration) -> tokio::time::Instant {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Could not listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; }
+            Err(e) => {
+                tracing::error!("Could not listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    tracing::info!("Shutting down within {:?}", timeout);
+    shutdown.cancel();
+    tokio::time::sleep(NOT_READY_PERIOD.min(timeout)).await;
+
+    let drain_period = deadline.saturating_duration_since(tokio::time::Instant::now());
+    tracing::info!("Waiting up to {:?} for requests in flight", drain_period);
+    handle.graceful_shutdown(Some(drain_period));
+    deadline
+}
+
+/// Drops histogram samples that were already rendered, until shutdown.
+async fn metrics_upkeep(metrics: PrometheusHandle, shutdown: CancellationToken) {
+    let mut interval = tokio::time::interval(Duration::from_secs(5));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => metrics.run_upkeep(),
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
+
+/// Only the configured origins may call the API from a browser, `*` allows any origin.
+fn cors_layer(config: &Config) -> anyhow::Result<CorsLayer> {
+    let methods = config.cors_allowed_methods.iter()
+        .map(|method| Method::from_bytes(method.to_uppercase().as_bytes()))
+        .collect::<Result<Vec<Method>, _>>()?;
+    let cors = CorsLayer::new()
+        .allow_methods(methods)
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, REQUEST_ID_HEADER])
+        .expose_headers([REQUEST_ID_HEADER, DEPRECATION_HEADER, header::LINK]);
+
+    if config.cors_allowed_origins.iter().any(|origin| origin == "*") {
+        return Ok(cors.allow_origin(Any));
+    }
+    let origins = config.cors_allowed_origins.iter()
+        .map(|origin| HeaderValue::from_str(origin))
+        .collect::<Result<Vec<HeaderValue>, _>>()?;
+    Ok(cors.allow_origin(origins))
 }
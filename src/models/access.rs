@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::models::profile::ProfileId;
+
+/// What a user may do with a profile. Patients own their data, caregivers help with
+/// taking doses and keeping stock, viewers only follow along.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Patient,
+    Caregiver,
+    Viewer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Read,
+    LogDose,
+    AddStock,
+    Manage,
+}
+
+impl Action {
+    fn description(&self) -> &'static str {
+        match self {
+            Action::Read => "read this profile",
+            Action::LogDose => "log doses",
+            Action::AddStock => "add stock",
+            Action::Manage => "change medicines, schedules, prescriptions or access",
+        }
+    }
+}
+
+impl Role {
+    pub fn allows(&self, action: Action) -> bool {
+        match self {
+            Role::Patient => true,
+            Role::Caregiver => matches!(action, Action::Read | Action::LogDose | Action::AddStock),
+            Role::Viewer => action == Action::Read,
+        }
+    }
+
+    /// Checks whether the role allows `action`, returning the reason when it doesn't.
+    pub fn check(&self, action: Action) -> Result<(), String> {
+        if self.allows(action) {
+            Ok(())
+        } else {
+            Err(format!("A {} may not {}", self.name(), action.description()))
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Role::Patient => "patient",
+            Role::Caregiver => "caregiver",
+            Role::Viewer => "viewer",
+        }
+    }
+}
+
+/// The role of the user identified by `subject` (the JWT subject) on a profile.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Grant {
+    #[schema(value_type = String)]
+    pub profile_id: ProfileId,
+    pub subject: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiGrant {
+    pub role: Role,
+}
+
+impl ApiGrant {
+    pub fn to_grant(&self, profile_id: ProfileId, subject: String) -> Grant {
+        Grant {
+            profile_id,
+            subject,
+            role: self.role,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patient_may_do_everything() {
+        for action in [Action::Read, Action::LogDose, Action::AddStock, Action::Manage] {
+            assert!(Role::Patient.allows(action));
+        }
+    }
+
+    #[test]
+    fn test_caregiver_may_log_doses_and_add_stock() {
+        assert!(Role::Caregiver.allows(Action::Read));
+        assert!(Role::Caregiver.allows(Action::LogDose));
+        assert!(Role::Caregiver.allows(Action::AddStock));
+        assert!(!Role::Caregiver.allows(Action::Manage));
+    }
+
+    #[test]
+    fn test_viewer_may_only_read() {
+        assert!(Role::Viewer.check(Action::Read).is_ok());
+        assert_eq!(Role::Viewer.check(Action::LogDose), Err("A viewer may not log doses".to_string()));
+        assert!(Role::Viewer.check(Action::AddStock).is_err());
+        assert!(Role::Viewer.check(Action::Manage).is_err());
+    }
+
+    #[test]
+    fn test_grant_serialization() {
+        let grant = ApiGrant { role: Role::Caregiver }.to_grant("profile-id".to_string(), "user-1".to_string());
+
+        let json = serde_json::to_string(&grant).unwrap();
+        assert!(json.contains("\"role\":\"caregiver\""));
+
+        let deserialized: Grant = serde_json::from_str(&json).unwrap();
+        assert_eq!(grant, deserialized);
+    }
+}
@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Prefix of API key secrets, followed by the key id and the random part.
+const SECRET_PREFIX: &str = "mk_";
+
+/// Resources a scope can give access to, combined with `read` or `write`.
+const RESOURCES: [&str; 8] = ["medicine", "schedule", "dosage", "prescription", "interaction", "grant", "profile", "audit"];
+
+/// A key for devices and integrations acting on behalf of `subject`, limited to
+/// its scopes. Only a hash of the secret is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub subject: String,
+    pub scopes: Vec<String>,
+    pub secret_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Creates a key, returning it together with its secret, which is not kept.
+    pub fn generate(name: String, subject: String, scopes: Vec<String>, expires_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> (Self, String) {
+        let id = Uuid::new_v4().simple().to_string();
+        let secret = format!("{}{}_{}{}", SECRET_PREFIX, id, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let key = Self {
+            id,
+            name,
+            subject,
+            scopes,
+            secret_hash: hash_secret(&secret),
+            created_at: now,
+            expires_at,
+            last_used_at: None,
+        };
+        (key, secret)
+    }
+
+    /// The id of the key a secret belongs to, if it looks like an API key secret.
+    pub fn id_from_secret(secret: &str) -> Option<&str> {
+        secret.strip_prefix(SECRET_PREFIX)?.split_once('_').map(|(id, _)| id)
+    }
+
+    pub fn verify(&self, secret: &str) -> bool {
+        secret_matches(secret, &self.secret_hash)
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// A `write` scope includes reading the same resource.
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| {
+            granted == scope || scope.strip_suffix(":read").is_some_and(|resource| *granted == format!("{}:write", resource))
+        })
+    }
+
+    pub fn info(&self) -> ApiKeyInfo {
+        ApiKeyInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            scopes: self.scopes.clone(),
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+}
+
+/// The hash of a secret, which is stored instead of the secret itself.
+pub fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Whether `secret` hashes to `secret_hash`.
+pub fn secret_matches(secret: &str, secret_hash: &str) -> bool {
+    // Compare without stopping at the first difference
+    let hash = hash_secret(secret);
+    hash.len() == secret_hash.len()
+        && hash.bytes().zip(secret_hash.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub fn is_valid_scope(scope: &str) -> bool {
+    match scope.split_once(':') {
+        Some((resource, access)) => RESOURCES.contains(&resource) && (access == "read" || access == "write"),
+        None => false,
+    }
+}
+
+/// The scope an API key needs for a request, e.g. `dosage:write` for
+/// `POST /profiles/:pid/dosage-history`. `None` for routes API keys can't be used on.
+pub fn required_scope(method: &str, path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    // All versions of the API need the same scopes
+    let segments = match segments.as_slice() {
+        ["v1" | "v2", rest @ ..] => rest,
+        all => all,
+    };
+    // Profile scoped routes work on the resource after the profile id
+    let segments = match segments {
+        ["profiles", _, resource @ ..] if !resource.is_empty() => resource,
+        all => all,
+    };
+    // A calendar subscription gives lasting access without the key, so only users
+    // who log in can create one
+    if segments.starts_with(&["schedules", "calendar", "subscription"]) {
+        return None;
+    }
+    let segment = match segments {
+        [first, ..] => *first,
+        [] => return None,
+    };
+    let resource = match segment {
+        "medicines" => "medicine",
+        "schedules" => "schedule",
+        "dosage-history" => "dosage",
+        "prescriptions" => "prescription",
+        "interactions" => "interaction",
+        "grants" => "grant",
+        "profiles" => "profile",
+        "audit" => "audit",
+        _ => return None,
+    };
+    let access = if method == "GET" { "read" } else { "write" };
+    Some(format!("{}:{}", resource, access))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A newly created key, the only time its secret is returned.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NewApiKey {
+    #[serde(flatten)]
+    pub key: ApiKeyInfo,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn create_test_key(scopes: &[&str]) -> (ApiKey, String) {
+        ApiKey::generate(
+            "Pill dispenser".to_string(),
+            "user-1".to_string(),
+            scopes.iter().map(|scope| scope.to_string()).collect(),
+            None,
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_generate_stores_only_a_hash() {
+        let (key, secret) = create_test_key(&["dosage:write"]);
+
+        assert!(secret.starts_with("mk_"));
+        assert!(!key.secret_hash.contains(&secret));
+        assert_eq!(ApiKey::id_from_secret(&secret), Some(key.id.as_str()));
+        assert!(key.verify(&secret));
+        assert!(!key.verify("mk_wrong_secret"));
+    }
+
+    #[test]
+    fn test_id_from_secret() {
+        assert_eq!(ApiKey::id_from_secret("mk_abc_def"), Some("abc"));
+        assert_eq!(ApiKey::id_from_secret("eyJhbGciOi.x.y"), None);
+        assert_eq!(ApiKey::id_from_secret("mk_abc"), None);
+    }
+
+    #[test]
+    fn test_is_expired_at() {
+        let now = Utc::now();
+        let (key, _) = create_test_key(&[]);
+        assert!(!key.is_expired_at(now));
+
+        let expiring = ApiKey { expires_at: Some(now), ..key };
+        assert!(!expiring.is_expired_at(now - Duration::seconds(1)));
+        assert!(expiring.is_expired_at(now));
+    }
+
+    #[test]
+    fn test_allows() {
+        let (key, _) = create_test_key(&["dosage:write", "schedule:read"]);
+
+        assert!(key.allows("dosage:write"));
+        assert!(key.allows("dosage:read"));
+        assert!(key.allows("schedule:read"));
+        assert!(!key.allows("schedule:write"));
+        assert!(!key.allows("medicine:read"));
+    }
+
+    #[test]
+    fn test_is_valid_scope() {
+        assert!(is_valid_scope("dosage:write"));
+        assert!(is_valid_scope("profile:read"));
+        assert!(!is_valid_scope("dosage:delete"));
+        assert!(!is_valid_scope("api-keys:write"));
+        assert!(!is_valid_scope("dosage"));
+    }
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(required_scope("POST", "/dosage-history"), Some("dosage:write".to_string()));
+        assert_eq!(required_scope("GET", "/schedules/daily/2024-01-01"), Some("schedule:read".to_string()));
+        assert_eq!(required_scope("POST", "/profiles/p1/medicines/m1/addStock"), Some("medicine:write".to_string()));
+        assert_eq!(required_scope("GET", "/profiles/p1"), Some("profile:read".to_string()));
+        assert_eq!(required_scope("GET", "/profiles"), Some("profile:read".to_string()));
+        assert_eq!(required_scope("POST", "/api-keys"), None);
+        assert_eq!(required_scope("GET", "/v1/medicines"), Some("medicine:read".to_string()));
+        assert_eq!(required_scope("GET", "/v2/profiles/p1/schedules/daily/2024-01-01"), Some("schedule:read".to_string()));
+        assert_eq!(required_scope("POST", "/v1/api-keys"), None);
+        assert_eq!(required_scope("GET", "/schedules/calendar.ics"), Some("schedule:read".to_string()));
+        assert_eq!(required_scope("POST", "/schedules/calendar/subscription"), None);
+        assert_eq!(required_scope("DELETE", "/v1/profiles/p1/schedules/calendar/subscription"), None);
+    }
+
+    #[test]
+    fn test_info_leaves_out_the_hash() {
+        let (key, secret) = create_test_key(&["dosage:write"]);
+
+        let json = serde_json::to_string(&NewApiKey { key: key.info(), secret }).unwrap();
+        assert!(json.contains("\"secret\":\"mk_"));
+        assert!(!json.contains("secret_hash"));
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use uuid::Uuid;
+use crate::models::profile::ProfileId;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    AddStock,
+    ConsumeStock,
+    CorrectStock,
+    DisposeStock,
+}
+
+/// A change to an entity of a profile, with the state before and after it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct AuditEntry {
+    pub id: String,
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    #[schema(value_type = String)]
+    pub profile_id: ProfileId,
+    pub entity: String,
+    pub entity_id: String,
+    pub action: AuditAction,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl AuditEntry {
+    pub fn new(
+        actor: String,
+        profile_id: ProfileId,
+        entity: &str,
+        entity_id: String,
+        action: AuditAction,
+        before: Option<serde_json::Value>,
+        after: Option<serde_json::Value>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            actor,
+            timestamp: Utc::now(),
+            profile_id,
+            entity: entity.to_string(),
+            entity_id,
+            action,
+            before,
+            after,
+        }
+    }
+}
+
+/// Filters of the audit log. `from` and `to` are RFC 3339 timestamps or dates.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// Kind of entity, such as `medicine`
+    pub entity: Option<String>,
+    /// Id of the entity
+    pub id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.entity.as_ref().is_none_or(|entity| *entity == entry.entity)
+            && self.id.as_ref().is_none_or(|id| *id == entry.entity_id)
+    }
+}
+
+/// Parses an RFC 3339 timestamp or a date. A date used as the end of a range
+/// includes that whole day.
+pub fn parse_audit_time(value: &str, end_of_range: bool) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let date = if end_of_range { date + Duration::days(1) } else { date };
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_entry(entity: &str, entity_id: &str) -> AuditEntry {
+        AuditEntry::new(
+            "user-1".to_string(),
+            "default".to_string(),
+            entity,
+            entity_id.to_string(),
+            AuditAction::Update,
+            Some(serde_json::json!({"stock": 10.0})),
+            Some(serde_json::json!({"stock": 40.0})),
+        )
+    }
+
+    #[test]
+    fn test_audit_query_matches() {
+        let entry = create_test_entry("medicine", "m1");
+
+        assert!(AuditQuery::default().matches(&entry));
+        assert!(AuditQuery { entity: Some("medicine".to_string()), id: Some("m1".to_string()), ..Default::default() }.matches(&entry));
+        assert!(!AuditQuery { entity: Some("schedule".to_string()), ..Default::default() }.matches(&entry));
+        assert!(!AuditQuery { id: Some("m2".to_string()), ..Default::default() }.matches(&entry));
+    }
+
+    #[test]
+    fn test_parse_audit_time() {
+        let start = parse_audit_time("2024-01-15", false).unwrap();
+        let end = parse_audit_time("2024-01-15", true).unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-01-15T00:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-01-16T00:00:00+00:00");
+
+        let exact = parse_audit_time("2024-01-15T08:30:00+01:00", true).unwrap();
+        assert_eq!(exact.to_rfc3339(), "2024-01-15T07:30:00+00:00");
+
+        assert!(parse_audit_time("yesterday", false).is_none());
+    }
+
+    #[test]
+    fn test_audit_entry_serialization() {
+        let entry = create_test_entry("medicine", "m1");
+
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains("\"action\":\"update\""));
+
+        let deserialized: AuditEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(entry, deserialized);
+    }
+}
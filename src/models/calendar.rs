@@ -0,0 +1,552 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::models::api_key::{hash_secret, secret_matches};
+use crate::models::medicine::{Medicine, MedicineId};
+use crate::models::profile::ProfileId;
+use crate::models::schedule::{ApiMedicineSchedule, MedicineSchedule};
+use crate::models::warning::Warning;
+
+/// Prefix of calendar subscription tokens, followed by the profile id and the random part.
+const TOKEN_PREFIX: &str = "mc_";
+
+/// Lines of an iCalendar file are folded after this many bytes.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Lets calendar apps, which can't log in, fetch the schedules of a profile with a
+/// secret token in the URL. A profile has at most one, only a hash of the token is
+/// stored.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CalendarSubscription {
+    pub profile_id: ProfileId,
+    pub token_hash: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CalendarSubscription {
+    /// Creates a subscription, returning it together with its token, which is not kept.
+    pub fn generate(profile_id: ProfileId, created_by: String, now: DateTime<Utc>) -> (Self, String) {
+        let token = format!("{}{}_{}{}", TOKEN_PREFIX, profile_id, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let subscription = Self {
+            profile_id,
+            token_hash: hash_secret(&token),
+            created_by,
+            created_at: now,
+        };
+        (subscription, token)
+    }
+
+    /// The id of the profile a token belongs to, if it looks like a subscription token.
+    pub fn profile_from_token(token: &str) -> Option<&str> {
+        token.strip_prefix(TOKEN_PREFIX)?.rsplit_once('_').map(|(profile_id, _)| profile_id)
+    }
+
+    pub fn verify(&self, token: &str) -> bool {
+        secret_matches(token, &self.token_hash)
+    }
+
+    /// Path of the calendar feed of a token.
+    pub fn feed_path(token: &str) -> String {
+        format!("/v1/calendar/{}/schedules.ics", token)
+    }
+}
+
+/// A new subscription, the only time the path with its token is returned.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NewCalendarSubscription {
+    /// Path of the calendar feed, relative to the server
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The schedules as an iCalendar file, with an event repeating every day from
+/// `start` for each schedule and a reminder when a dose is due. Times are floating,
+/// so doses stay at the same local time wherever the calendar is. Schedules of
+/// medicines that no longer exist are left out.
+pub fn schedules_to_ics(schedules: &[MedicineSchedule], medicines: &HashMap<MedicineId, Medicine>, start: NaiveDate, now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//medicate-rust//Schedules//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Medicines".to_string(),
+    ];
+    let mut schedules = schedules.to_vec();
+    schedules.sort();
+    for schedule in schedules {
+        let (Some(medicine), Ok(time)) = (medicines.get(&schedule.medicine_id), NaiveTime::parse_from_str(&schedule.time, "%H:%M")) else {
+            continue;
+        };
+        let summary = format!("Take {} × {}", schedule.amount, medicine.name);
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@medicate-rust", schedule.id),
+            format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART:{}", start.and_time(time).format("%Y%m%dT%H%M%S")),
+            "DURATION:PT15M".to_string(),
+            "RRULE:FREQ=DAILY".to_string(),
+            format!("SUMMARY:{}", escape_text(&summary)),
+            format!("DESCRIPTION:{}", escape_text(&format!("{} × {} {} {}", schedule.amount, medicine.name, medicine.dose, medicine.unit))),
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".to_string(),
+            "TRIGGER:PT0M".to_string(),
+            format!("DESCRIPTION:{}", escape_text(&summary)),
+            "END:VALARM".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    
+    lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits a line longer than 75 bytes over several, each continuation starting with
+/// a space. Characters aren't split.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+/// An event of an iCalendar file, with the properties an import looks at.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IcsEvent {
+    pub summary: String,
+    pub start: Option<String>,
+    /// Time zone of `start` when it is a local time in a named zone
+    pub start_tzid: Option<String>,
+    pub rrule: Option<String>,
+}
+
+impl IcsEvent {
+    /// Time of day the event starts at in `timezone`. Times without a zone are
+    /// taken as they are, like calendar apps do.
+    fn start_time(&self, timezone: Tz) -> Option<NaiveTime> {
+        let value = self.start.as_deref()?;
+        if let Some(utc) = value.strip_suffix('Z') {
+            let start = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+            return Some(start.and_utc().with_timezone(&timezone).time());
+        }
+        let start = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+        match self.start_tzid.as_deref().and_then(|tzid| tzid.parse::<Tz>().ok()) {
+            Some(zone) => Some(zone.from_local_datetime(&start).earliest()?.with_timezone(&timezone).time()),
+            None => Some(start.time()),
+        }
+    }
+
+    /// Checks that the event repeats every day without end, the only recurrence a
+    /// schedule has.
+    fn check_recurrence(&self) -> Result<(), ImportSkipReason> {
+        let Some(rrule) = &self.rrule else {
+            return Err(ImportSkipReason::NotDaily);
+        };
+        let parts: HashMap<String, &str> = rrule.split(';')
+            .filter_map(|part| part.split_once('='))
+            .map(|(name, value)| (name.to_uppercase(), value))
+            .collect();
+        let daily = parts.get("FREQ").is_some_and(|freq| freq.eq_ignore_ascii_case("DAILY"))
+            && parts.get("INTERVAL").is_none_or(|interval| *interval == "1")
+            && !parts.keys().any(|name| name.starts_with("BY"));
+        if !daily {
+            return Err(ImportSkipReason::NotDaily);
+        }
+        if parts.contains_key("COUNT") || parts.contains_key("UNTIL") {
+            return Err(ImportSkipReason::Bounded);
+        }
+        Ok(())
+    }
+
+    /// The amount and the name of the medicine in the summary, which is either just
+    /// the name or like `Take 2 × Paracetamol` as in the exported calendar. The
+    /// amount is `None` when it isn't a positive number.
+    fn dose(&self) -> (Option<f64>, &str) {
+        let summary = self.summary.trim();
+        let summary = summary.strip_prefix("Take ").unwrap_or(summary);
+        let mut words = summary.splitn(3, ' ');
+        if let (Some(amount), Some("×" | "x"), Some(name)) = (words.next(), words.next(), words.next()) {
+            if let Ok(amount) = amount.parse::<f64>() {
+                return (Some(amount).filter(|amount| amount.is_finite() && *amount > 0.0), name.trim());
+            }
+        }
+        (Some(1.0), summary)
+    }
+}
+
+/// The events of an iCalendar file, or `None` when it isn't one. Components inside
+/// events, like alarms, are left out.
+pub fn parse_ics_events(ics: &str) -> Option<Vec<IcsEvent>> {
+    let lines = unfold_lines(ics.trim_start_matches('\u{feff}'));
+    if !lines.first()?.eq_ignore_ascii_case("BEGIN:VCALENDAR") {
+        return None;
+    }
+    
+    let mut events = Vec::new();
+    let mut event: Option<IcsEvent> = None;
+    let mut nested = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name.split(';');
+        let name = params.next().unwrap_or_default().to_uppercase();
+        match (name.as_str(), value.to_uppercase().as_str(), event.as_mut()) {
+            ("BEGIN", "VEVENT", None) => event = Some(IcsEvent::default()),
+            ("END", "VEVENT", Some(_)) if nested == 0 => events.extend(event.take()),
+            ("BEGIN", _, Some(_)) => nested += 1,
+            ("END", _, Some(_)) => nested -= 1,
+            (_, _, Some(event)) if nested == 0 => match name.as_str() {
+                "SUMMARY" => event.summary = unescape_text(value),
+                "DTSTART" => {
+                    event.start = Some(value.to_string());
+                    event.start_tzid = params
+                        .filter_map(|param| param.split_once('='))
+                        .find(|(param, _)| param.eq_ignore_ascii_case("TZID"))
+                        .map(|(_, tzid)| tzid.trim_matches('"').to_string());
+                }
+                "RRULE" => event.rrule = Some(value.to_string()),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    Some(events)
+}
+
+/// Joins folded lines back together.
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Whether an import creates the schedules, by default it only reports them.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct IcsImportQuery {
+    /// Create the schedules instead of only reporting them
+    #[serde(default)]
+    pub commit: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSkipReason {
+    /// No medicine of the profile has the name in the summary
+    UnknownMedicine,
+    /// The amount in the summary isn't a positive number
+    InvalidAmount,
+    /// The event doesn't repeat every day
+    NotDaily,
+    /// The event stops repeating after a number of times or at a date
+    Bounded,
+    /// The event has no start time, e.g. because it lasts all day
+    NoStartTime,
+    /// The medicine is already scheduled at this time
+    AlreadyScheduled,
+}
+
+/// A schedule an import creates from an event.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ImportedSchedule {
+    pub summary: String,
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub medicine_name: String,
+    pub time: String,
+    pub amount: f64,
+    /// Id of the created schedule, once committed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule_id: Option<String>,
+}
+
+impl ImportedSchedule {
+    pub fn to_api_schedule(&self) -> ApiMedicineSchedule {
+        ApiMedicineSchedule {
+            time: self.time.clone(),
+            medicine_id: self.medicine_id.clone(),
+            amount: self.amount,
+        }
+    }
+}
+
+/// An event an import leaves out.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UnmatchedEvent {
+    pub summary: String,
+    pub reason: ImportSkipReason,
+}
+
+/// What importing a calendar does, or did when `committed`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct IcsImportReport {
+    pub committed: bool,
+    pub created: Vec<ImportedSchedule>,
+    pub unmatched: Vec<UnmatchedEvent>,
+    /// Interactions the created schedules add to the current regimen
+    #[serde(default)]
+    pub warnings: Vec<Warning>,
+}
+
+impl IcsImportReport {
+    /// The schedules the events would create next to the `existing` ones. Summaries
+    /// are matched to medicines by name, ignoring case, and otherwise to the longest
+    /// medicine name they contain. Times are converted to `timezone`.
+    pub fn plan(events: &[IcsEvent], medicines: &[Medicine], existing: &[MedicineSchedule], timezone: Tz) -> Self {
+        let mut scheduled: Vec<(MedicineId, String)> = existing.iter()
+            .map(|schedule| (schedule.medicine_id.clone(), schedule.time.clone()))
+            .collect();
+        let mut created = Vec::new();
+        let mut unmatched = Vec::new();
+        for event in events {
+            match Self::plan_event(event, medicines, &scheduled, timezone) {
+                Ok(schedule) => {
+                    scheduled.push((schedule.medicine_id.clone(), schedule.time.clone()));
+                    created.push(schedule);
+                }
+                Err(reason) => unmatched.push(UnmatchedEvent { summary: event.summary.clone(), reason }),
+            }
+        }
+        
+        Self {
+            committed: false,
+            created,
+            unmatched,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn plan_event(event: &IcsEvent, medicines: &[Medicine], scheduled: &[(MedicineId, String)], timezone: Tz) -> Result<ImportedSchedule, ImportSkipReason> {
+        event.check_recurrence()?;
+        let time = event.start_time(timezone)
+            .ok_or(ImportSkipReason::NoStartTime)?
+            .format("%H:%M")
+            .to_string();
+        let (amount, name) = event.dose();
+        let medicine = match_medicine(name, medicines).ok_or(ImportSkipReason::UnknownMedicine)?;
+        let amount = amount.ok_or(ImportSkipReason::InvalidAmount)?;
+        if scheduled.iter().any(|(medicine_id, scheduled_time)| *medicine_id == medicine.id && *scheduled_time == time) {
+            return Err(ImportSkipReason::AlreadyScheduled);
+        }
+        
+        Ok(ImportedSchedule {
+            summary: event.summary.clone(),
+            medicine_id: medicine.id.clone(),
+            medicine_name: medicine.name.clone(),
+            time,
+            amount,
+            schedule_id: None,
+        })
+    }
+}
+
+fn match_medicine<'a>(name: &str, medicines: &'a [Medicine]) -> Option<&'a Medicine> {
+    let name = name.to_lowercase();
+    medicines.iter()
+        .find(|medicine| medicine.name.to_lowercase() == name)
+        .or_else(|| {
+            medicines.iter()
+                .filter(|medicine| !medicine.name.is_empty() && name.contains(&medicine.name.to_lowercase()))
+                .max_by_key(|medicine| medicine.name.len())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedules_to_ics() {
+        let medicine = Medicine::with_id("med1".to_string(), "Paracetamol, forte".to_string(), 500.0, "mg".to_string(), 20.0);
+        let medicines = HashMap::from([(medicine.id.clone(), medicine)]);
+        let schedules = vec![
+            MedicineSchedule::with_id("s2".to_string(), "20:30".to_string(), "med1".to_string(), 2.0),
+            MedicineSchedule::with_id("s1".to_string(), "08:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::with_id("s3".to_string(), "08:00".to_string(), "deleted".to_string(), 1.0),
+            MedicineSchedule::with_id("s4".to_string(), "morning".to_string(), "med1".to_string(), 1.0),
+        ];
+        let start = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let now = start.and_hms_opt(12, 0, 0).unwrap().and_utc();
+        
+        let ics = schedules_to_ics(&schedules, &medicines, start, now);
+        
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert_eq!(ics.matches("BEGIN:VALARM").count(), 2);
+        assert_eq!(ics.matches("RRULE:FREQ=DAILY").count(), 2);
+        let first = ics.find("UID:s1@medicate-rust").unwrap();
+        let second = ics.find("UID:s2@medicate-rust").unwrap();
+        assert!(first < second);
+        assert!(ics.contains("DTSTART:20240115T080000\r\n"));
+        assert!(ics.contains("DTSTART:20240115T203000\r\n"));
+        assert!(ics.contains("DTSTAMP:20240115T120000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Take 2 × Paracetamol\\, forte\r\n"));
+        assert!(ics.contains("DESCRIPTION:1 × Paracetamol\\, forte 500 mg\r\n"));
+    }
+
+    #[test]
+    fn test_fold_line() {
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold_line(&line);
+        
+        assert!(folded.split("\r\n").all(|part| part.len() <= MAX_LINE_LENGTH));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert_eq!(fold_line("SUMMARY:short"), "SUMMARY:short");
+    }
+
+    #[test]
+    fn test_subscription_token() {
+        let now = Utc::now();
+        let (subscription, token) = CalendarSubscription::generate("3f2b1c4e-aaaa-bbbb-cccc-1234567890ab".to_string(), "user-1".to_string(), now);
+        
+        assert_eq!(CalendarSubscription::profile_from_token(&token), Some("3f2b1c4e-aaaa-bbbb-cccc-1234567890ab"));
+        assert!(subscription.verify(&token));
+        assert!(!subscription.verify(&format!("{}x", token)));
+        assert!(!token.contains(&subscription.token_hash));
+        assert_eq!(CalendarSubscription::profile_from_token("mk_abc_def"), None);
+        assert_eq!(CalendarSubscription::feed_path("mc_default_x"), "/v1/calendar/mc_default_x/schedules.ics");
+    }
+
+    #[test]
+    fn test_parse_ics_events() {
+        let ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nSUMMARY:Paracetamol\\, fo\r\n rte\r\nDTSTART;TZID=Europe/Amsterdam:20240115T080000\r\nRRULE:FREQ=DAILY\r\nBEGIN:VALARM\r\nDESCRIPTION:Reminder\r\nEND:VALARM\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:Dentist\r\nDTSTART;VALUE=DATE:20240116\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        
+        let events = parse_ics_events(ics).unwrap();
+        
+        assert_eq!(events, vec![
+            IcsEvent {
+                summary: "Paracetamol, forte".to_string(),
+                start: Some("20240115T080000".to_string()),
+                start_tzid: Some("Europe/Amsterdam".to_string()),
+                rrule: Some("FREQ=DAILY".to_string()),
+            },
+            IcsEvent {
+                summary: "Dentist".to_string(),
+                start: Some("20240116".to_string()),
+                start_tzid: None,
+                rrule: None,
+            },
+        ]);
+        assert_eq!(parse_ics_events("not a calendar"), None);
+        assert_eq!(parse_ics_events(""), None);
+    }
+
+    #[test]
+    fn test_plan_ics_import() {
+        let medicines = vec![
+            Medicine::with_id("med1".to_string(), "Paracetamol".to_string(), 500.0, "mg".to_string(), 20.0),
+            Medicine::with_id("med2".to_string(), "Vitamin D".to_string(), 10.0, "mcg".to_string(), 30.0),
+        ];
+        let existing = vec![MedicineSchedule::with_id("s1".to_string(), "08:00".to_string(), "med2".to_string(), 1.0)];
+        let event = |summary: &str, start: &str, rrule: Option<&str>| IcsEvent {
+            summary: summary.to_string(),
+            start: Some(start.to_string()),
+            start_tzid: None,
+            rrule: rrule.map(str::to_string),
+        };
+        let events = vec![
+            event("Take 2 × Paracetamol", "20240115T080000", Some("FREQ=DAILY")),
+            event("vitamin d with breakfast", "20240115T073000Z", Some("FREQ=DAILY;INTERVAL=1")),
+            event("Vitamin D", "20240115T080000", Some("FREQ=DAILY")),
+            event("Paracetamol", "20240115T080000", Some("FREQ=DAILY")),
+            event("Ibuprofen", "20240115T080000", Some("FREQ=DAILY")),
+            event("Paracetamol", "20240115T200000", Some("FREQ=WEEKLY")),
+            event("Paracetamol", "20240115T200000", Some("FREQ=DAILY;INTERVAL=2")),
+            event("Paracetamol", "20240115", Some("FREQ=DAILY")),
+            event("Paracetamol", "20240115T200000", Some("FREQ=DAILY;UNTIL=20200101")),
+            event("Paracetamol", "20240115T200000", Some("FREQ=DAILY;COUNT=10")),
+            event("NaN × Paracetamol", "20240115T200000", Some("FREQ=DAILY")),
+            event("Take inf × Paracetamol", "20240115T200000", Some("FREQ=DAILY")),
+            event("Take 0 x Paracetamol", "20240115T200000", Some("FREQ=DAILY")),
+            event("-1 × Paracetamol", "20240115T200000", Some("FREQ=DAILY")),
+        ];
+        
+        let report = IcsImportReport::plan(&events, &medicines, &existing, "Europe/Amsterdam".parse().unwrap());
+        
+        assert!(!report.committed);
+        let created: Vec<_> = report.created.iter()
+            .map(|schedule| (schedule.medicine_id.as_str(), schedule.time.as_str(), schedule.amount))
+            .collect();
+        assert_eq!(created, vec![("med1", "08:00", 2.0), ("med2", "08:30", 1.0)]);
+        let reasons: Vec<_> = report.unmatched.iter().map(|event| event.reason).collect();
+        assert_eq!(reasons, vec![
+            ImportSkipReason::AlreadyScheduled,
+            ImportSkipReason::AlreadyScheduled,
+            ImportSkipReason::UnknownMedicine,
+            ImportSkipReason::NotDaily,
+            ImportSkipReason::NotDaily,
+            ImportSkipReason::NoStartTime,
+            ImportSkipReason::Bounded,
+            ImportSkipReason::Bounded,
+            ImportSkipReason::InvalidAmount,
+            ImportSkipReason::InvalidAmount,
+            ImportSkipReason::InvalidAmount,
+            ImportSkipReason::InvalidAmount,
+        ]);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_import_exported_calendar() {
+        let medicine = Medicine::with_id("med1".to_string(), "Paracetamol".to_string(), 500.0, "mg".to_string(), 20.0);
+        let schedules = vec![
+            MedicineSchedule::with_id("s1".to_string(), "08:00".to_string(), "med1".to_string(), 1.5),
+            MedicineSchedule::with_id("s2".to_string(), "20:30".to_string(), "med1".to_string(), 2.0),
+        ];
+        let start = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let ics = schedules_to_ics(&schedules, &HashMap::from([(medicine.id.clone(), medicine.clone())]), start, Utc::now());
+        
+        let events = parse_ics_events(&ics).unwrap();
+        let report = IcsImportReport::plan(&events, &[medicine], &[], Tz::UTC);
+        
+        let imported: Vec<_> = report.created.iter().map(|schedule| schedule.to_api_schedule()).collect();
+        assert_eq!(imported.len(), 2);
+        for (schedule, api_schedule) in schedules.iter().zip(imported) {
+            assert_eq!((api_schedule.time.as_str(), api_schedule.amount), (schedule.time.as_str(), schedule.amount));
+        }
+        assert!(report.unmatched.is_empty());
+    }
+}
@@ -1,17 +1,20 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use chrono::{DateTime, Utc};
 use crate::models::medicine::MedicineId;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct DosageHistory {
     pub id: String,
     pub datetime: DateTime<Utc>,
+    #[schema(value_type = String)]
     pub medicine_id: MedicineId,
     pub description: String,
     pub amount: f64,
 }
 
 impl DosageHistory {
+    #[cfg(test)]
     pub fn with_id(id: String, datetime: DateTime<Utc>, medicine_id: MedicineId, amount: f64) -> Self {
         Self {
             id,
@@ -35,7 +38,7 @@ impl DosageHistory {
 
 impl std::cmp::PartialOrd for DosageHistory {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.datetime.cmp(&other.datetime))
+        Some(self.cmp(other))
     }
 }
 
@@ -47,26 +50,25 @@ impl std::cmp::Ord for DosageHistory {
 
 impl std::cmp::Eq for DosageHistory {}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ApiDosageHistory {
     pub date: String,
     pub time: String,
+    #[schema(value_type = String)]
     pub medicine_id: MedicineId,
     pub amount: f64,
 }
 
 impl ApiDosageHistory {
-    pub fn to_dosage_history(&self, id: String, description: String) -> Result<DosageHistory, chrono::ParseError> {
+    pub fn datetime(&self) -> Result<DateTime<Utc>, chrono::ParseError> {
         let date_time_str = format!("{}T{}:00Z", self.date, self.time);
-        let datetime = DateTime::parse_from_rfc3339(&date_time_str)?.with_timezone(&Utc);
+        Ok(DateTime::parse_from_rfc3339(&date_time_str)?.with_timezone(&Utc))
+    }
+
+    pub fn to_dosage_history(&self, id: String, description: String) -> Result<DosageHistory, chrono::ParseError> {
+        let datetime = self.datetime()?;
         
-        Ok(DosageHistory {
-            id,
-            datetime,
-            medicine_id: self.medicine_id.clone(),
-            description,
-            amount: self.amount,
-        })
+        Ok(DosageHistory::with_id_and_description(id, datetime, self.medicine_id.clone(), description, self.amount))
     }
 }
 
@@ -118,7 +120,7 @@ mod tests {
             300.0
         );
         
-        let mut histories = vec![history1.clone(), history2.clone(), history3.clone()];
+        let mut histories = [history1.clone(), history2.clone(), history3.clone()];
         histories.sort_by(|a, b| b.cmp(a));
         
         // Should be sorted by datetime (descending)
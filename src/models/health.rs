@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+/// The outcome of checking a single dependency.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct HealthCheck {
+    pub status: HealthStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HealthCheck {
+    pub fn up(latency: Duration) -> Self {
+        Self {
+            status: HealthStatus::Up,
+            latency_ms: Some(latency.as_secs_f64() * 1000.0),
+            error: None,
+        }
+    }
+
+    pub fn down(error: String) -> Self {
+        Self {
+            status: HealthStatus::Down,
+            latency_ms: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Whether the service can handle requests, it's only up when all its checks are.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Readiness {
+    pub status: HealthStatus,
+    pub checks: BTreeMap<String, HealthCheck>,
+}
+
+impl Readiness {
+    pub fn new(checks: BTreeMap<String, HealthCheck>) -> Self {
+        let status = if checks.values().all(|check| check.status == HealthStatus::Up) {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        };
+        Self { status, checks }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == HealthStatus::Up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_is_down_when_any_check_is() {
+        let up = Readiness::new(BTreeMap::from([
+            ("storage".to_string(), HealthCheck::up(Duration::from_micros(1500))),
+        ]));
+        assert!(up.is_ready());
+        assert_eq!(up.checks["storage"].latency_ms, Some(1.5));
+
+        let down = Readiness::new(BTreeMap::from([
+            ("storage".to_string(), HealthCheck::up(Duration::from_millis(1))),
+            ("other".to_string(), HealthCheck::down("Connection refused".to_string())),
+        ]));
+        assert!(!down.is_ready());
+    }
+
+    #[test]
+    fn test_readiness_serialization() {
+        let readiness = Readiness::new(BTreeMap::from([
+            ("storage".to_string(), HealthCheck::down("Connection refused".to_string())),
+        ]));
+
+        let json = serde_json::to_value(&readiness).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "status": "down",
+            "checks": {"storage": {"status": "down", "error": "Connection refused"}},
+        }));
+    }
+}
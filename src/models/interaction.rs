@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use crate::models::medicine::{Medicine, MedicineId};
+use crate::models::warning::Warning;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Interaction {
+    pub ingredient_a: String,
+    pub ingredient_b: String,
+    pub severity: String,
+    pub description: String,
+}
+
+impl Interaction {
+    fn involves(&self, a: &str, b: &str) -> bool {
+        let (x, y) = (normalize(&self.ingredient_a), normalize(&self.ingredient_b));
+        (x == a && y == b) || (x == b && y == a)
+    }
+}
+
+/// Known interactions between active ingredients, loaded from a local CSV or JSON file.
+#[derive(Debug, Clone, Default)]
+pub struct InteractionTable {
+    interactions: Vec<Interaction>,
+}
+
+impl InteractionTable {
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        Self { interactions }
+    }
+
+    /// Loads a `.json` file with a list of interactions, or a CSV file with the header
+    /// `ingredient_a,ingredient_b,severity,description`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read interactions file {}", path.display()))?;
+        let table = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&content),
+            _ => Self::from_csv(&content),
+        };
+        table.with_context(|| format!("Invalid interactions file {}", path.display()))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        Ok(Self::new(serde_json::from_str(content)?))
+    }
+
+    /// Parses CSV rows after the header line. The description is the last column
+    /// and may contain commas.
+    pub fn from_csv(content: &str) -> Result<Self> {
+        let mut interactions = Vec::new();
+        for (number, line) in content.lines().enumerate().skip(1) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let columns: Vec<&str> = line.splitn(4, ',').map(|column| column.trim().trim_matches('"')).collect();
+            if columns.len() != 4 {
+                return Err(anyhow!("Line {} has {} columns, expected 4", number + 1, columns.len()));
+            }
+            interactions.push(Interaction {
+                ingredient_a: columns[0].to_string(),
+                ingredient_b: columns[1].to_string(),
+                severity: columns[2].to_string(),
+                description: columns[3].to_string(),
+            });
+        }
+        Ok(Self::new(interactions))
+    }
+
+    pub fn len(&self) -> usize {
+        self.interactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interactions.is_empty()
+    }
+
+    /// Finds interactions and shared active ingredients between `medicine` and each of `others`.
+    pub fn check(&self, medicine: &Medicine, others: &[Medicine]) -> Vec<InteractionFinding> {
+        others.iter()
+            .filter(|other| other.id != medicine.id)
+            .flat_map(|other| self.check_pair(medicine, other))
+            .collect()
+    }
+
+    /// Finds interactions and shared active ingredients between all medicines in a regimen.
+    pub fn check_regimen(&self, medicines: &[Medicine]) -> Vec<InteractionFinding> {
+        medicines.iter()
+            .enumerate()
+            .flat_map(|(index, medicine)| self.check(medicine, &medicines[index + 1..]))
+            .collect()
+    }
+
+    fn check_pair(&self, a: &Medicine, b: &Medicine) -> Vec<InteractionFinding> {
+        let mut findings = Vec::new();
+        for ingredient_a in a.active_ingredients.iter().map(|ingredient| normalize(ingredient)) {
+            for ingredient_b in b.active_ingredients.iter().map(|ingredient| normalize(ingredient)) {
+                if ingredient_a == ingredient_b {
+                    findings.push(InteractionFinding::new(
+                        FindingKind::DuplicateIngredient, a, b, &ingredient_a, &ingredient_b, "moderate",
+                        format!("Both medicines contain {}", ingredient_a),
+                    ));
+                }
+                for interaction in self.interactions.iter().filter(|interaction| interaction.involves(&ingredient_a, &ingredient_b)) {
+                    findings.push(InteractionFinding::new(
+                        FindingKind::Interaction, a, b, &ingredient_a, &ingredient_b, &interaction.severity,
+                        interaction.description.clone(),
+                    ));
+                }
+            }
+        }
+        findings
+    }
+}
+
+fn normalize(ingredient: &str) -> String {
+    ingredient.trim().to_lowercase()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    Interaction,
+    DuplicateIngredient,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct InteractionFinding {
+    pub kind: FindingKind,
+    pub medicine_ids: (MedicineId, MedicineId),
+    pub medicine_names: (String, String),
+    pub ingredients: (String, String),
+    pub severity: String,
+    pub description: String,
+}
+
+impl InteractionFinding {
+    fn new(kind: FindingKind, a: &Medicine, b: &Medicine, ingredient_a: &str, ingredient_b: &str, severity: &str, description: String) -> Self {
+        Self {
+            kind,
+            medicine_ids: (a.id.clone(), b.id.clone()),
+            medicine_names: (a.name.clone(), b.name.clone()),
+            ingredients: (ingredient_a.to_string(), ingredient_b.to_string()),
+            severity: severity.to_string(),
+            description,
+        }
+    }
+
+    pub fn to_warning(&self) -> Warning {
+        let code = match self.kind {
+            FindingKind::Interaction => "interaction",
+            FindingKind::DuplicateIngredient => "duplicate_ingredient",
+        };
+        Warning::new(code, format!(
+            "{} and {} ({} severity): {}",
+            self.medicine_names.0, self.medicine_names.1, self.severity, self.description
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn medicine_with(name: &str, ingredients: &[&str]) -> Medicine {
+        Medicine {
+            active_ingredients: ingredients.iter().map(|ingredient| ingredient.to_string()).collect(),
+            ..Medicine::new(name.to_string(), 100.0, "mg".to_string(), 10.0)
+        }
+    }
+
+    fn create_test_table() -> InteractionTable {
+        InteractionTable::from_csv(
+            "ingredient_a,ingredient_b,severity,description\n\
+             warfarin,ibuprofen,major,Increased risk of bleeding, avoid combination\n"
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let table = create_test_table();
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.interactions[0].ingredient_a, "warfarin");
+        assert_eq!(table.interactions[0].description, "Increased risk of bleeding, avoid combination");
+    }
+
+    #[test]
+    fn test_from_csv_invalid_line() {
+        let result = InteractionTable::from_csv("ingredient_a,ingredient_b,severity,description\nwarfarin,ibuprofen\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let table = InteractionTable::from_json(
+            r#"[{"ingredient_a":"a","ingredient_b":"b","severity":"minor","description":"d"}]"#
+        ).unwrap();
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.interactions[0].severity, "minor");
+    }
+
+    #[test]
+    fn test_check_interaction_either_order() {
+        let table = create_test_table();
+        let painkiller = medicine_with("Ibuprofen", &["Ibuprofen"]);
+        let anticoagulant = medicine_with("Marevan", &["warfarin"]);
+
+        let findings = table.check(&painkiller, std::slice::from_ref(&anticoagulant));
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, FindingKind::Interaction);
+        assert_eq!(findings[0].severity, "major");
+        assert_eq!(findings[0].medicine_ids, (painkiller.id.clone(), anticoagulant.id.clone()));
+        assert_eq!(findings[0].to_warning().code, "interaction");
+    }
+
+    #[test]
+    fn test_check_duplicate_ingredient() {
+        let table = InteractionTable::default();
+        let first = medicine_with("Panadol", &["paracetamol"]);
+        let second = medicine_with("Combination cold remedy", &["Paracetamol", "caffeine"]);
+
+        let findings = table.check(&first, &[first.clone(), second]);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, FindingKind::DuplicateIngredient);
+        assert_eq!(findings[0].to_warning().code, "duplicate_ingredient");
+    }
+
+    #[test]
+    fn test_check_regimen() {
+        let table = create_test_table();
+        let regimen = vec![
+            medicine_with("Ibuprofen", &["ibuprofen"]),
+            medicine_with("Marevan", &["warfarin"]),
+            medicine_with("Vitamin D", &["colecalciferol"]),
+        ];
+
+        let findings = table.check_regimen(&regimen);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].medicine_names, ("Ibuprofen".to_string(), "Marevan".to_string()));
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::models::medicine::MedicineId;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StockMovementKind {
+    /// The stock a medicine had when its ledger was started.
+    Opening,
+    Refill,
+    DoseConsumed,
+    Correction,
+    Disposal,
+}
+
+/// A single change of stock. `quantity` is negative for stock going out, `balance`
+/// is the stock after the movement.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct LedgerEntry {
+    pub id: String,
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub timestamp: DateTime<Utc>,
+    pub kind: StockMovementKind,
+    pub quantity: f64,
+    pub balance: f64,
+    pub reason: Option<String>,
+    pub batch_id: Option<String>,
+}
+
+impl LedgerEntry {
+    pub fn new(medicine_id: MedicineId, kind: StockMovementKind, quantity: f64, balance: f64, reason: Option<String>, batch_id: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            medicine_id,
+            timestamp: Utc::now(),
+            kind,
+            quantity,
+            balance,
+            reason,
+            batch_id,
+        }
+    }
+}
+
+/// The stock according to the ledger, the sum of all movements.
+pub fn ledger_balance(entries: &[LedgerEntry]) -> f64 {
+    entries.iter().map(|entry| entry.quantity).sum()
+}
+
+/// A physical count of the stock of a medicine.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiStockCount {
+    pub counted: f64,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Reconciliation {
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub expected: f64,
+    pub counted: f64,
+    pub difference: f64,
+    /// The correction booked for the difference, if there was any.
+    pub correction: Option<LedgerEntry>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_balance() {
+        let entries = [
+            LedgerEntry::new("m1".to_string(), StockMovementKind::Opening, 10.0, 10.0, None, None),
+            LedgerEntry::new("m1".to_string(), StockMovementKind::Refill, 30.0, 40.0, None, Some("batch".to_string())),
+            LedgerEntry::new("m1".to_string(), StockMovementKind::DoseConsumed, -2.0, 38.0, None, None),
+            LedgerEntry::new("m1".to_string(), StockMovementKind::Correction, -1.0, 37.0, Some("Dropped one".to_string()), None),
+        ];
+
+        assert_eq!(ledger_balance(&entries), 37.0);
+        assert_eq!(ledger_balance(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_ledger_entry_serialization() {
+        let entry = LedgerEntry::new("m1".to_string(), StockMovementKind::DoseConsumed, -1.0, 9.0, None, None);
+
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains("\"kind\":\"dose_consumed\""));
+
+        let deserialized: LedgerEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(entry, deserialized);
+    }
+}
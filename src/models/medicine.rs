@@ -1,15 +1,26 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::NaiveDate;
 use uuid::Uuid;
+use crate::models::prn::PrnConfig;
+use crate::models::stock::{ExpiringStock, StockBatch};
 
 pub type MedicineId = String;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct Medicine {
+    #[schema(value_type = String)]
     pub id: MedicineId,
     pub name: String,
     pub dose: f64,
     pub unit: String,
     pub stock: f64,
+    #[serde(default)]
+    pub batches: Vec<StockBatch>,
+    #[serde(default)]
+    pub prn: Option<PrnConfig>,
+    #[serde(default)]
+    pub active_ingredients: Vec<String>,
 }
 
 impl Medicine {
@@ -20,6 +31,9 @@ impl Medicine {
             dose,
             unit,
             stock,
+            batches: Vec::new(),
+            prn: None,
+            active_ingredients: Vec::new(),
         }
     }
 
@@ -30,27 +44,115 @@ impl Medicine {
             dose,
             unit,
             stock,
+            batches: Vec::new(),
+            prn: None,
+            active_ingredients: Vec::new(),
         }
     }
 
-    pub fn add_stock(&self, amount: f64) -> Self {
+    pub fn add_stock(&self, batch: StockBatch) -> Self {
+        let mut batches = self.batches.clone();
+        let stock = self.stock + batch.quantity;
+        batches.push(batch);
+        batches.sort();
         Self {
-            stock: self.stock + amount,
+            stock,
+            batches,
             ..self.clone()
         }
     }
 
-    // pub fn reduce_stock(&self, amount: f64) -> Option<Self> {
-    //     let new_stock = self.stock - amount;
-    //     if new_stock < 0.0 {
-    //         None
-    //     } else {
-    //         Some(Self {
-    //             stock: new_stock,
-    //             ..self.clone()
-    //         })
-    //     }
-    // }
+    /// Stock that is not covered by any batch, e.g. stock recorded before batches existed.
+    pub fn untracked_stock(&self) -> f64 {
+        let batched: f64 = self.batches.iter().map(|batch| batch.quantity).sum();
+        (self.stock - batched).max(0.0)
+    }
+
+    /// Takes `amount` out of stock. Untracked stock is used up first, after that the
+    /// batches are consumed first-expiry-first-out. Stock never drops below zero.
+    pub fn consume_stock(&self, amount: f64) -> Self {
+        let mut remaining = (amount - self.untracked_stock()).max(0.0);
+        let mut batches = self.batches.clone();
+        batches.sort();
+        for batch in batches.iter_mut() {
+            let taken = remaining.min(batch.quantity);
+            batch.quantity -= taken;
+            remaining -= taken;
+        }
+        batches.retain(|batch| batch.quantity > 0.0);
+        Self {
+            stock: (self.stock - amount).max(0.0),
+            batches,
+            ..self.clone()
+        }
+    }
+
+    /// Corrects the stock by `difference`. Stock that turns out to be missing is taken
+    /// out FEFO, extra stock is untracked.
+    pub fn adjust_stock(&self, difference: f64) -> Self {
+        if difference < 0.0 {
+            self.consume_stock(-difference)
+        } else {
+            Self {
+                stock: self.stock + difference,
+                ..self.clone()
+            }
+        }
+    }
+
+    /// The batches that expired before `today`.
+    pub fn expired_batches(&self, today: NaiveDate) -> Vec<StockBatch> {
+        self.batches.iter()
+            .filter(|batch| batch.expiry_date.is_some_and(|expiry| expiry < today))
+            .cloned()
+            .collect()
+    }
+
+    /// Takes a batch out of stock, e.g. to dispose of it.
+    pub fn remove_batch(&self, batch_id: &str) -> Self {
+        let removed: f64 = self.batches.iter()
+            .filter(|batch| batch.id == batch_id)
+            .map(|batch| batch.quantity)
+            .sum();
+        Self {
+            stock: (self.stock - removed).max(0.0),
+            batches: self.batches.iter().filter(|batch| batch.id != batch_id).cloned().collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the stock runs out within `days` at `daily_usage`. Medicines that aren't
+    /// scheduled never need to be reordered.
+    pub fn needs_reorder(&self, daily_usage: f64, days: i64) -> bool {
+        daily_usage > 0.0 && self.stock < daily_usage * days as f64
+    }
+
+    /// Applies the batches of the stored medicine to this (updated) medicine. When the
+    /// new stock is below what the batches hold, the difference is taken out FEFO.
+    pub fn with_batches_of(&self, previous: &Medicine) -> Self {
+        let batched: f64 = previous.batches.iter().map(|batch| batch.quantity).sum();
+        let medicine = Self {
+            stock: self.stock.max(batched),
+            batches: previous.batches.clone(),
+            ..self.clone()
+        };
+        if self.stock < batched {
+            medicine.consume_stock(batched - self.stock)
+        } else {
+            medicine
+        }
+    }
+
+    pub fn expiring_before(&self, date: NaiveDate) -> Vec<ExpiringStock> {
+        self.batches.iter()
+            .filter(|batch| batch.expiry_date.is_some_and(|expiry| expiry <= date))
+            .map(|batch| ExpiringStock {
+                medicine_id: self.id.clone(),
+                medicine_name: self.name.clone(),
+                batch: batch.clone(),
+            })
+            .collect()
+    }
 
     // pub fn to_api_medicine(&self) -> ApiMedicine {
     //     ApiMedicine {
@@ -70,7 +172,7 @@ impl std::fmt::Display for Medicine {
 
 impl std::cmp::PartialOrd for Medicine {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.name.cmp(&other.name))
+        Some(self.cmp(other))
     }
 }
 
@@ -82,26 +184,38 @@ impl std::cmp::Ord for Medicine {
 
 impl std::cmp::Eq for Medicine {}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ApiMedicine {
     pub name: String,
     pub dose: f64,
     pub unit: String,
     pub stock: f64,
+    #[serde(default)]
+    pub prn: Option<PrnConfig>,
+    #[serde(default)]
+    pub active_ingredients: Vec<String>,
 }
 
 impl ApiMedicine {
     pub fn to_medicine(&self) -> Medicine {
-        Medicine::new(
-            self.name.clone(),
-            self.dose,
-            self.unit.clone(),
-            self.stock,
-        )
+        Medicine {
+            prn: self.prn.clone(),
+            active_ingredients: self.active_ingredients.clone(),
+            ..Medicine::new(
+                self.name.clone(),
+                self.dose,
+                self.unit.clone(),
+                self.stock,
+            )
+        }
     }
 
     pub fn to_medicine_with_id(&self, id: MedicineId) -> Medicine {
-        Medicine::with_id(id, self.name.clone(), self.dose, self.unit.clone(), self.stock)
+        Medicine {
+            prn: self.prn.clone(),
+            active_ingredients: self.active_ingredients.clone(),
+            ..Medicine::with_id(id, self.name.clone(), self.dose, self.unit.clone(), self.stock)
+        }
     }
 }
 
@@ -152,13 +266,145 @@ mod tests {
             100.0
         );
         
-        let updated = medicine.add_stock(50.0);
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let updated = medicine.add_stock(StockBatch::new(50.0, None, None, received));
         assert_eq!(updated.stock, 150.0);
         assert_eq!(updated.name, medicine.name);
         assert_eq!(updated.dose, medicine.dose);
         assert_eq!(updated.unit, medicine.unit);
     }
 
+    #[test]
+    fn test_medicine_add_stock_batch() {
+        let medicine = Medicine::new("Paracetamol".to_string(), 500.0, "mg".to_string(), 10.0);
+        let expiry = NaiveDate::from_ymd_opt(2025, 1, 31).unwrap();
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let updated = medicine.add_stock(StockBatch::new(20.0, Some("LOT-7".to_string()), Some(expiry), received));
+
+        assert_eq!(updated.stock, 30.0);
+        assert_eq!(updated.batches.len(), 1);
+        assert_eq!(updated.batches[0].lot, Some("LOT-7".to_string()));
+        assert_eq!(updated.untracked_stock(), 10.0);
+    }
+
+    #[test]
+    fn test_medicine_consume_stock_fefo() {
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let early = StockBatch::new(5.0, Some("EARLY".to_string()), NaiveDate::from_ymd_opt(2024, 6, 1), received);
+        let late = StockBatch::new(10.0, Some("LATE".to_string()), NaiveDate::from_ymd_opt(2025, 6, 1), received);
+        let medicine = Medicine::new("Ibuprofen".to_string(), 200.0, "mg".to_string(), 2.0)
+            .add_stock(late)
+            .add_stock(early);
+
+        // 2 untracked, then 5 from the earliest batch, then 1 from the next
+        let updated = medicine.consume_stock(8.0);
+
+        assert_eq!(updated.stock, 9.0);
+        assert_eq!(updated.batches.len(), 1);
+        assert_eq!(updated.batches[0].lot, Some("LATE".to_string()));
+        assert_eq!(updated.batches[0].quantity, 9.0);
+    }
+
+    #[test]
+    fn test_medicine_consume_stock_never_negative() {
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let medicine = Medicine::new("Aspirin".to_string(), 500.0, "mg".to_string(), 3.0)
+            .add_stock(StockBatch::new(2.0, None, None, received));
+
+        let updated = medicine.consume_stock(10.0);
+
+        assert_eq!(updated.stock, 0.0);
+        assert!(updated.batches.is_empty());
+    }
+
+    #[test]
+    fn test_medicine_with_batches_of() {
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let stored = Medicine::new("Aspirin".to_string(), 500.0, "mg".to_string(), 0.0)
+            .add_stock(StockBatch::new(10.0, None, NaiveDate::from_ymd_opt(2024, 6, 1), received))
+            .add_stock(StockBatch::new(10.0, None, NaiveDate::from_ymd_opt(2025, 6, 1), received));
+
+        let raised = Medicine::with_id(stored.id.clone(), "Aspirin".to_string(), 500.0, "mg".to_string(), 25.0)
+            .with_batches_of(&stored);
+        assert_eq!(raised.stock, 25.0);
+        assert_eq!(raised.batches.len(), 2);
+        assert_eq!(raised.untracked_stock(), 5.0);
+
+        let lowered = Medicine::with_id(stored.id.clone(), "Aspirin".to_string(), 500.0, "mg".to_string(), 15.0)
+            .with_batches_of(&stored);
+        assert_eq!(lowered.stock, 15.0);
+        assert_eq!(lowered.batches.len(), 2);
+        assert_eq!(lowered.batches[0].quantity, 5.0);
+    }
+
+    #[test]
+    fn test_medicine_expiring_before() {
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let medicine = Medicine::new("Aspirin".to_string(), 500.0, "mg".to_string(), 0.0)
+            .add_stock(StockBatch::new(10.0, Some("A".to_string()), NaiveDate::from_ymd_opt(2024, 2, 1), received))
+            .add_stock(StockBatch::new(10.0, Some("B".to_string()), NaiveDate::from_ymd_opt(2024, 9, 1), received))
+            .add_stock(StockBatch::new(10.0, Some("C".to_string()), None, received));
+
+        let expiring = medicine.expiring_before(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].medicine_id, medicine.id);
+        assert_eq!(expiring[0].batch.lot, Some("A".to_string()));
+    }
+
+    #[test]
+    fn test_medicine_adjust_stock() {
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let medicine = Medicine::new("Aspirin".to_string(), 500.0, "mg".to_string(), 0.0)
+            .add_stock(StockBatch::new(10.0, None, NaiveDate::from_ymd_opt(2024, 6, 1), received));
+
+        let missing = medicine.adjust_stock(-3.0);
+        assert_eq!(missing.stock, 7.0);
+        assert_eq!(missing.batches[0].quantity, 7.0);
+
+        let found = medicine.adjust_stock(2.0);
+        assert_eq!(found.stock, 12.0);
+        assert_eq!(found.untracked_stock(), 2.0);
+    }
+
+    #[test]
+    fn test_medicine_needs_reorder() {
+        let medicine = Medicine::new("Aspirin".to_string(), 500.0, "mg".to_string(), 10.0);
+
+        assert!(medicine.needs_reorder(2.0, 7));
+        assert!(!medicine.needs_reorder(1.0, 7));
+        assert!(!medicine.needs_reorder(0.0, 7));
+    }
+
+    #[test]
+    fn test_medicine_expired_batches_and_remove_batch() {
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let medicine = Medicine::new("Aspirin".to_string(), 500.0, "mg".to_string(), 5.0)
+            .add_stock(StockBatch::new(10.0, Some("A".to_string()), NaiveDate::from_ymd_opt(2024, 2, 1), received))
+            .add_stock(StockBatch::new(10.0, Some("B".to_string()), NaiveDate::from_ymd_opt(2024, 9, 1), received));
+
+        let expired = medicine.expired_batches(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].lot, Some("A".to_string()));
+        // Stock expiring today can still be used
+        assert!(medicine.expired_batches(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()).is_empty());
+
+        let updated = medicine.remove_batch(&expired[0].id);
+        assert_eq!(updated.stock, 15.0);
+        assert_eq!(updated.batches.len(), 1);
+    }
+
+    #[test]
+    fn test_medicine_deserialize_without_batches() {
+        let json = r#"{"id":"id-1","name":"Aspirin","dose":500.0,"unit":"mg","stock":10.0}"#;
+        let medicine: Medicine = serde_json::from_str(json).unwrap();
+
+        assert!(medicine.batches.is_empty());
+        assert!(medicine.active_ingredients.is_empty());
+        assert_eq!(medicine.untracked_stock(), 10.0);
+    }
+
     #[test]
     fn test_medicine_display() {
         let medicine = Medicine::new(
@@ -178,7 +424,7 @@ mod tests {
         let medicine2 = Medicine::new("Ibuprofen".to_string(), 200.0, "mg".to_string(), 50.0);
         let medicine3 = Medicine::new("Paracetamol".to_string(), 500.0, "mg".to_string(), 75.0);
         
-        let mut medicines = vec![medicine1.clone(), medicine2.clone(), medicine3.clone()];
+        let mut medicines = [medicine1.clone(), medicine2.clone(), medicine3.clone()];
         medicines.sort();
         
         assert_eq!(medicines[0].name, "Aspirin");
@@ -193,6 +439,8 @@ mod tests {
             dose: 250.0,
             unit: "mg".to_string(),
             stock: 25.0,
+            prn: None,
+            active_ingredients: vec![],
         };
         
         let medicine = api_medicine.to_medicine();
@@ -211,6 +459,8 @@ mod tests {
             dose: 250.0,
             unit: "mg".to_string(),
             stock: 25.0,
+            prn: None,
+            active_ingredients: vec![],
         };
         
         let id = "custom-id-123".to_string();
@@ -242,6 +492,27 @@ mod tests {
         assert_eq!(medicine.id, deserialized.id);
     }
 
+    #[test]
+    fn test_api_medicine_to_medicine_with_prn() {
+        let prn = PrnConfig {
+            max_single_dose: Some(2.0),
+            max_doses_per_day: Some(4),
+            min_interval_minutes: Some(360),
+            warn_only: false,
+        };
+        let api_medicine = ApiMedicine {
+            name: "Paracetamol".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 20.0,
+            prn: Some(prn.clone()),
+            active_ingredients: vec![],
+        };
+
+        assert_eq!(api_medicine.to_medicine().prn, Some(prn.clone()));
+        assert_eq!(api_medicine.to_medicine_with_id("id".to_string()).prn, Some(prn));
+    }
+
     #[test]
     fn test_api_medicine_serialization() {
         let api_medicine = ApiMedicine {
@@ -249,6 +520,8 @@ mod tests {
             dose: 300.0,
             unit: "mg".to_string(),
             stock: 75.0,
+            prn: None,
+            active_ingredients: vec![],
         };
         
         let json = serde_json::to_string(&api_medicine).unwrap();
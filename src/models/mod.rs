@@ -1,7 +1,31 @@
 pub mod medicine;
 pub mod schedule;
 pub mod dosage_history;
+pub mod stock;
+pub mod ledger;
+pub mod prescription;
+pub mod prn;
+pub mod interaction;
+pub mod profile;
+pub mod access;
+pub mod api_key;
+pub mod audit;
+pub mod warning;
+pub mod health;
+pub mod calendar;
 
 pub use medicine::*;
 pub use schedule::*;
-pub use dosage_history::*; 
\ No newline at end of file
+pub use dosage_history::*;
+pub use stock::*;
+pub use ledger::*;
+pub use prescription::*;
+pub use prn::*;
+pub use interaction::*;
+pub use profile::*;
+pub use access::*;
+pub use api_key::*;
+pub use audit::*;
+pub use warning::*;
+pub use health::*;
+pub use calendar::*; 
\ No newline at end of file
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{Days, NaiveDate};
+use thiserror::Error;
+use uuid::Uuid;
+use crate::models::medicine::{Medicine, MedicineId};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Prescription {
+    pub id: String,
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub prescriber: String,
+    pub issue_date: NaiveDate,
+    pub quantity_per_fill: f64,
+    pub refills_remaining: u32,
+    pub expiry_date: NaiveDate,
+}
+
+impl Prescription {
+    pub fn new(medicine_id: MedicineId, prescriber: String, issue_date: NaiveDate, quantity_per_fill: f64, refills_remaining: u32, expiry_date: NaiveDate) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            medicine_id,
+            prescriber,
+            issue_date,
+            quantity_per_fill,
+            refills_remaining,
+            expiry_date,
+        }
+    }
+
+    pub fn is_expired_on(&self, date: NaiveDate) -> bool {
+        self.expiry_date < date
+    }
+
+    /// Returns the prescription with one refill less, or `None` when no refill can be used on `date`.
+    pub fn use_refill(&self, date: NaiveDate) -> Option<Self> {
+        if self.refills_remaining == 0 || self.is_expired_on(date) {
+            return None;
+        }
+        Some(Self {
+            refills_remaining: self.refills_remaining - 1,
+            ..self.clone()
+        })
+    }
+
+    /// Checks whether a refill will still be available when the current stock of `medicine`
+    /// runs out, given its scheduled `daily_usage`. Returns an alert when it won't be.
+    pub fn refill_alert(&self, medicine: &Medicine, daily_usage: f64, today: NaiveDate) -> Option<RefillAlert> {
+        if daily_usage <= 0.0 {
+            return None;
+        }
+        // Stock that lasts beyond any representable date never runs out
+        let days_of_stock = (medicine.stock / daily_usage).floor() as u64;
+        let stock_runout_date = today.checked_add_days(Days::new(days_of_stock))?;
+
+        let reason = if self.refills_remaining == 0 {
+            "no refills remaining"
+        } else if self.is_expired_on(stock_runout_date) {
+            "prescription expires before stock runs out"
+        } else {
+            return None;
+        };
+
+        Some(RefillAlert {
+            prescription_id: self.id.clone(),
+            medicine_id: medicine.id.clone(),
+            medicine_name: medicine.name.clone(),
+            prescriber: self.prescriber.clone(),
+            refills_remaining: self.refills_remaining,
+            expiry_date: self.expiry_date,
+            stock: medicine.stock,
+            daily_usage,
+            stock_runout_date,
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// The prescription can't be filled, it has no refills left or is no longer valid.
+#[derive(Debug, Error)]
+#[error("No refill of prescription {0} can be used")]
+pub struct NoRefillLeft(pub String);
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiPrescription {
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub prescriber: String,
+    pub issue_date: NaiveDate,
+    pub quantity_per_fill: f64,
+    pub refills_remaining: u32,
+    pub expiry_date: NaiveDate,
+}
+
+impl ApiPrescription {
+    pub fn to_prescription(&self) -> Prescription {
+        Prescription::new(
+            self.medicine_id.clone(),
+            self.prescriber.clone(),
+            self.issue_date,
+            self.quantity_per_fill,
+            self.refills_remaining,
+            self.expiry_date,
+        )
+    }
+
+    pub fn to_prescription_with_id(&self, id: String) -> Prescription {
+        Prescription {
+            id,
+            ..self.to_prescription()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct RefillAlert {
+    pub prescription_id: String,
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub medicine_name: String,
+    pub prescriber: String,
+    pub refills_remaining: u32,
+    pub expiry_date: NaiveDate,
+    pub stock: f64,
+    pub daily_usage: f64,
+    pub stock_runout_date: NaiveDate,
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn create_test_prescription(refills_remaining: u32, expiry_date: NaiveDate) -> Prescription {
+        Prescription::new(
+            "medicine-id".to_string(),
+            "Dr. Jansen".to_string(),
+            date(2024, 1, 1),
+            30.0,
+            refills_remaining,
+            expiry_date,
+        )
+    }
+
+    #[test]
+    fn test_prescription_new() {
+        let prescription = create_test_prescription(2, date(2025, 1, 1));
+
+        assert!(!prescription.id.is_empty());
+        assert_eq!(prescription.medicine_id, "medicine-id");
+        assert_eq!(prescription.prescriber, "Dr. Jansen");
+        assert_eq!(prescription.quantity_per_fill, 30.0);
+        assert_eq!(prescription.refills_remaining, 2);
+    }
+
+    #[test]
+    fn test_prescription_use_refill() {
+        let prescription = create_test_prescription(1, date(2025, 1, 1));
+
+        let refilled = prescription.use_refill(date(2024, 6, 1)).unwrap();
+        assert_eq!(refilled.refills_remaining, 0);
+        assert!(refilled.use_refill(date(2024, 6, 1)).is_none());
+    }
+
+    #[test]
+    fn test_prescription_use_refill_expired() {
+        let prescription = create_test_prescription(3, date(2024, 5, 31));
+
+        assert!(prescription.use_refill(date(2024, 5, 31)).is_some());
+        assert!(prescription.use_refill(date(2024, 6, 1)).is_none());
+    }
+
+    #[test]
+    fn test_refill_alert_no_refills_remaining() {
+        let prescription = create_test_prescription(0, date(2025, 1, 1));
+        let medicine = Medicine::with_id("medicine-id".to_string(), "Aspirin".to_string(), 100.0, "mg".to_string(), 10.0);
+
+        let alert = prescription.refill_alert(&medicine, 2.0, date(2024, 1, 1)).unwrap();
+
+        assert_eq!(alert.stock_runout_date, date(2024, 1, 6));
+        assert_eq!(alert.reason, "no refills remaining");
+        assert_eq!(alert.medicine_name, "Aspirin");
+    }
+
+    #[test]
+    fn test_refill_alert_expires_before_runout() {
+        let prescription = create_test_prescription(2, date(2024, 1, 20));
+        let medicine = Medicine::with_id("medicine-id".to_string(), "Aspirin".to_string(), 100.0, "mg".to_string(), 30.0);
+
+        let alert = prescription.refill_alert(&medicine, 1.0, date(2024, 1, 1)).unwrap();
+
+        assert_eq!(alert.stock_runout_date, date(2024, 1, 31));
+        assert_eq!(alert.reason, "prescription expires before stock runs out");
+    }
+
+    #[test]
+    fn test_refill_alert_covered() {
+        let prescription = create_test_prescription(2, date(2025, 1, 1));
+        let medicine = Medicine::with_id("medicine-id".to_string(), "Aspirin".to_string(), 100.0, "mg".to_string(), 30.0);
+
+        assert!(prescription.refill_alert(&medicine, 1.0, date(2024, 1, 1)).is_none());
+        // Unscheduled medicines have no projected run-out date
+        let empty = create_test_prescription(0, date(2025, 1, 1));
+        assert!(empty.refill_alert(&medicine, 0.0, date(2024, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn test_refill_alert_stock_beyond_any_date() {
+        let prescription = create_test_prescription(0, date(2025, 1, 1));
+        let medicine = Medicine::with_id("medicine-id".to_string(), "Aspirin".to_string(), 100.0, "mg".to_string(), 1e12);
+
+        assert!(prescription.refill_alert(&medicine, 1e-9, date(2024, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn test_api_prescription_to_prescription_with_id() {
+        let api_prescription = ApiPrescription {
+            medicine_id: "medicine-id".to_string(),
+            prescriber: "Dr. Jansen".to_string(),
+            issue_date: date(2024, 1, 1),
+            quantity_per_fill: 60.0,
+            refills_remaining: 5,
+            expiry_date: date(2025, 1, 1),
+        };
+
+        let prescription = api_prescription.to_prescription_with_id("custom-id".to_string());
+
+        assert_eq!(prescription.id, "custom-id");
+        assert_eq!(prescription.quantity_per_fill, 60.0);
+        assert_eq!(prescription.refills_remaining, 5);
+        assert_eq!(prescription.expiry_date, date(2025, 1, 1));
+    }
+
+    #[test]
+    fn test_prescription_serialization() {
+        let prescription = create_test_prescription(1, date(2025, 1, 1));
+
+        let json = serde_json::to_string(&prescription).unwrap();
+        let deserialized: Prescription = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(prescription, deserialized);
+        assert!(json.contains("\"expiry_date\":\"2025-01-01\""));
+    }
+}
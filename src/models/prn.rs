@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Duration, Utc};
+use crate::models::dosage_history::DosageHistory;
+use crate::models::medicine::MedicineId;
+use crate::models::warning::Warning;
+
+/// Longest `min_interval_minutes` that can be set, a week.
+pub const MAX_MIN_INTERVAL_MINUTES: i64 = 7 * 24 * 60;
+
+/// Safety limits for a medicine that is taken as needed (pro re nata) instead of on a schedule.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct PrnConfig {
+    pub max_single_dose: Option<f64>,
+    pub max_doses_per_day: Option<u32>,
+    pub min_interval_minutes: Option<i64>,
+    /// Record doses that exceed the limits with a warning instead of rejecting them.
+    #[serde(default)]
+    pub warn_only: bool,
+}
+
+impl PrnConfig {
+    /// Checks that the limits can be met, returning the reason when they can't.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_doses_per_day == Some(0) {
+            return Err("max_doses_per_day must be at least 1".to_string());
+        }
+        if let Some(minutes) = self.min_interval_minutes {
+            if !(0..=MAX_MIN_INTERVAL_MINUTES).contains(&minutes) {
+                return Err(format!("min_interval_minutes must be between 0 and {}", MAX_MIN_INTERVAL_MINUTES));
+            }
+        }
+        Ok(())
+    }
+
+    /// The daily limit, limits of 0 stored before they were rejected are ignored.
+    fn daily_limit(&self) -> Option<u32> {
+        self.max_doses_per_day.filter(|max| *max > 0)
+    }
+
+    fn min_interval(&self) -> Option<Duration> {
+        self.min_interval_minutes.map(|minutes| Duration::minutes(minutes.clamp(0, MAX_MIN_INTERVAL_MINUTES)))
+    }
+
+    /// Checks a new dose of `amount` at `datetime` against the limits, given the earlier doses
+    /// of the same medicine. Returns a warning for every limit that would be exceeded.
+    pub fn check(&self, history: &[DosageHistory], datetime: DateTime<Utc>, amount: f64) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+
+        if let Some(max) = self.max_single_dose {
+            if amount > max {
+                warnings.push(Warning::new(
+                    "max_single_dose",
+                    format!("A single dose of {} exceeds the maximum of {}", amount, max),
+                ));
+            }
+        }
+
+        if let Some(max) = self.daily_limit() {
+            let window_start = datetime - Duration::hours(24);
+            let doses = history.iter()
+                .filter(|dose| dose.datetime > window_start && dose.datetime <= datetime)
+                .count();
+            if doses as u32 >= max {
+                warnings.push(Warning::new(
+                    "max_doses_per_day",
+                    format!("{} doses were already taken in the 24 hours before, the maximum is {}", doses, max),
+                ));
+            }
+        }
+
+        if let Some(interval) = self.min_interval() {
+            let too_close = history.iter()
+                .any(|dose| (dose.datetime - datetime).abs() < interval);
+            if too_close {
+                warnings.push(Warning::new(
+                    "min_interval",
+                    format!("Doses must be at least {} minutes apart", interval.num_minutes()),
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    /// The earliest moment from `now` on at which a new dose stays within the interval and
+    /// daily limits.
+    pub fn next_allowed(&self, history: &[DosageHistory], now: DateTime<Utc>) -> DateTime<Utc> {
+        let mut next = now;
+
+        if let Some(interval) = self.min_interval() {
+            if let Some(last) = history.iter().map(|dose| dose.datetime).filter(|datetime| *datetime <= now).max() {
+                next = next.max(last + interval);
+            }
+        }
+
+        if let Some(max) = self.daily_limit() {
+            let mut recent: Vec<DateTime<Utc>> = history.iter()
+                .map(|dose| dose.datetime)
+                .filter(|datetime| *datetime > now - Duration::hours(24) && *datetime <= now)
+                .collect();
+            recent.sort();
+            if recent.len() >= max as usize {
+                // Wait until enough doses have left the 24 hour window
+                let index = recent.len() - max as usize;
+                next = next.max(recent[index] + Duration::hours(24));
+            }
+        }
+
+        next
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct NextAllowedDose {
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub allowed_now: bool,
+    pub next_allowed: DateTime<Utc>,
+    pub doses_last_24h: u32,
+    pub max_doses_per_day: Option<u32>,
+}
+
+impl NextAllowedDose {
+    pub fn new(medicine_id: MedicineId, prn: Option<&PrnConfig>, history: &[DosageHistory], now: DateTime<Utc>) -> Self {
+        let next_allowed = prn.map_or(now, |prn| prn.next_allowed(history, now));
+        let doses_last_24h = history.iter()
+            .filter(|dose| dose.datetime > now - Duration::hours(24) && dose.datetime <= now)
+            .count() as u32;
+        Self {
+            medicine_id,
+            allowed_now: next_allowed <= now,
+            next_allowed,
+            doses_last_24h,
+            max_doses_per_day: prn.and_then(|prn| prn.max_doses_per_day),
+        }
+    }
+}
+
+/// Body of the response when a dose is refused because it exceeds the PRN limits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct PrnLimitExceeded {
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub violations: Vec<Warning>,
+    pub next_allowed: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn dose_at(hour: u32, minute: u32) -> DosageHistory {
+        let datetime = Utc.with_ymd_and_hms(2024, 1, 15, hour, minute, 0).unwrap();
+        DosageHistory::with_id(format!("dose-{}-{}", hour, minute), datetime, "med1".to_string(), 1.0)
+    }
+
+    fn create_test_config() -> PrnConfig {
+        PrnConfig {
+            max_single_dose: Some(2.0),
+            max_doses_per_day: Some(3),
+            min_interval_minutes: Some(240),
+            warn_only: false,
+        }
+    }
+
+    #[test]
+    fn test_check_within_limits() {
+        let history = vec![dose_at(6, 0)];
+        let at = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        assert!(create_test_config().check(&history, at, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_check_max_single_dose() {
+        let at = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        let warnings = create_test_config().check(&[], at, 3.0);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "max_single_dose");
+    }
+
+    #[test]
+    fn test_check_max_doses_per_day_and_interval() {
+        let history = vec![dose_at(0, 0), dose_at(5, 0), dose_at(10, 0)];
+        let at = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        let warnings = create_test_config().check(&history, at, 1.0);
+        let codes: Vec<&str> = warnings.iter().map(|warning| warning.code.as_str()).collect();
+
+        assert_eq!(codes, vec!["max_doses_per_day", "min_interval"]);
+    }
+
+    #[test]
+    fn test_next_allowed_min_interval() {
+        let history = vec![dose_at(10, 0)];
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        let next = create_test_config().next_allowed(&history, now);
+
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 15, 14, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_next_allowed_max_doses_per_day() {
+        let history = vec![dose_at(0, 0), dose_at(5, 0), dose_at(10, 0)];
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 18, 0, 0).unwrap();
+
+        let next = create_test_config().next_allowed(&history, now);
+
+        // The dose at midnight leaves the 24 hour window first
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 16, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = |max_doses_per_day, min_interval_minutes| PrnConfig { max_doses_per_day, min_interval_minutes, ..create_test_config() };
+
+        assert!(create_test_config().validate().is_ok());
+        assert!(config(None, None).validate().is_ok());
+        assert!(config(Some(1), Some(MAX_MIN_INTERVAL_MINUTES)).validate().is_ok());
+        assert!(config(Some(0), None).validate().is_err());
+        assert!(config(None, Some(-1)).validate().is_err());
+        assert!(config(None, Some(i64::MAX)).validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_limits_do_not_panic() {
+        // Stored before such limits were rejected
+        let config = PrnConfig {
+            max_single_dose: None,
+            max_doses_per_day: Some(0),
+            min_interval_minutes: Some(i64::MAX),
+            warn_only: false,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        assert_eq!(config.next_allowed(&[], now), now);
+        let next = config.next_allowed(&[dose_at(10, 0)], now);
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 22, 10, 0, 0).unwrap());
+        let codes: Vec<String> = config.check(&[dose_at(10, 0)], now, 1.0).into_iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, vec!["min_interval"]);
+    }
+
+    #[test]
+    fn test_next_allowed_dose_without_prn() {
+        let history = vec![dose_at(10, 0)];
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        let next = NextAllowedDose::new("med1".to_string(), None, &history, now);
+
+        assert!(next.allowed_now);
+        assert_eq!(next.next_allowed, now);
+        assert_eq!(next.doses_last_24h, 1);
+        assert_eq!(next.max_doses_per_day, None);
+    }
+
+    #[test]
+    fn test_next_allowed_dose_with_prn() {
+        let history = vec![dose_at(10, 0)];
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+        let config = create_test_config();
+
+        let next = NextAllowedDose::new("med1".to_string(), Some(&config), &history, now);
+
+        assert!(!next.allowed_now);
+        assert_eq!(next.max_doses_per_day, Some(3));
+    }
+
+    #[test]
+    fn test_prn_config_deserialize_defaults() {
+        let config: PrnConfig = serde_json::from_str(r#"{"max_single_dose":1000.0,"max_doses_per_day":4,"min_interval_minutes":null}"#).unwrap();
+
+        assert_eq!(config.max_single_dose, Some(1000.0));
+        assert_eq!(config.max_doses_per_day, Some(4));
+        assert_eq!(config.min_interval_minutes, None);
+        assert!(!config.warn_only);
+    }
+}
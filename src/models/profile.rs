@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub type ProfileId = String;
+
+/// Profile used by the routes that are not scoped to a profile.
+pub const DEFAULT_PROFILE_ID: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Profile {
+    #[schema(value_type = String)]
+    pub id: ProfileId,
+    pub name: String,
+}
+
+impl Profile {
+    pub fn new(name: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+        }
+    }
+
+    pub fn with_id(id: ProfileId, name: String) -> Self {
+        Self { id, name }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiProfile {
+    pub name: String,
+}
+
+impl ApiProfile {
+    pub fn to_profile(&self) -> Profile {
+        Profile::new(self.name.clone())
+    }
+
+    pub fn to_profile_with_id(&self, id: ProfileId) -> Profile {
+        Profile::with_id(id, self.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_new() {
+        let profile = Profile::new("Grandma".to_string());
+
+        assert_eq!(profile.name, "Grandma");
+        assert!(!profile.id.is_empty());
+        assert_ne!(profile.id, DEFAULT_PROFILE_ID);
+    }
+
+    #[test]
+    fn test_api_profile_to_profile_with_id() {
+        let api_profile = ApiProfile { name: "Kid".to_string() };
+
+        let profile = api_profile.to_profile_with_id("profile-id".to_string());
+
+        assert_eq!(profile.id, "profile-id");
+        assert_eq!(profile.name, "Kid");
+    }
+
+    #[test]
+    fn test_profile_serialization() {
+        let profile = Profile::new("Grandpa".to_string());
+
+        let json = serde_json::to_string(&profile).unwrap();
+        let deserialized: Profile = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(profile, deserialized);
+    }
+}
@@ -1,13 +1,19 @@
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use chrono::{NaiveDate, NaiveTime};
+use chrono_tz::Tz;
+use std::collections::HashMap;
 use uuid::Uuid;
+use crate::models::dosage_history::DosageHistory;
 use crate::models::medicine::{MedicineId, Medicine};
 
 // pub type ScheduleId = String;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct MedicineSchedule {
     pub id: String,
     pub time: String,
+    #[schema(value_type = String)]
     pub medicine_id: MedicineId,
     pub description: String,
     pub amount: f64,
@@ -37,10 +43,7 @@ impl MedicineSchedule {
 
 impl std::cmp::PartialOrd for MedicineSchedule {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let time_cmp = self.time.replace(":", "").parse::<i32>()
-            .unwrap_or(0)
-            .cmp(&other.time.replace(":", "").parse::<i32>().unwrap_or(0));
-        Some(time_cmp)
+        Some(self.cmp(other))
     }
 }
 
@@ -54,9 +57,10 @@ impl std::cmp::Ord for MedicineSchedule {
 
 impl std::cmp::Eq for MedicineSchedule {}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ApiMedicineSchedule {
     pub time: String,
+    #[schema(value_type = String)]
     pub medicine_id: MedicineId,
     pub amount: f64,
 }
@@ -75,7 +79,35 @@ impl ApiMedicineSchedule {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Total amount of a medicine taken per day according to the schedules.
+pub fn daily_usage(schedules: &[MedicineSchedule], medicine_id: &str) -> f64 {
+    schedules.iter()
+        .filter(|schedule| schedule.medicine_id == medicine_id)
+        .map(|schedule| schedule.amount)
+        .sum()
+}
+
+/// The number of doses scheduled on `date` up to `time`, both in `timezone`, that
+/// weren't logged. A logged dose of a medicine counts for any of its scheduled times
+/// that day.
+pub fn missed_doses(schedules: &[MedicineSchedule], history: &[DosageHistory], date: NaiveDate, time: NaiveTime, timezone: Tz) -> usize {
+    let mut due: HashMap<&str, usize> = HashMap::new();
+    for schedule in schedules {
+        if NaiveTime::parse_from_str(&schedule.time, "%H:%M").is_ok_and(|scheduled| scheduled <= time) {
+            *due.entry(schedule.medicine_id.as_str()).or_default() += 1;
+        }
+    }
+    due.into_iter()
+        .map(|(medicine_id, count)| {
+            let taken = history.iter()
+                .filter(|dose| dose.medicine_id == medicine_id && dose.datetime.with_timezone(&timezone).date_naive() == date)
+                .count();
+            count.saturating_sub(taken)
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct DailySchedule {
     pub time: String,
     pub medicines: Vec<(Option<Medicine>, f64)>,
@@ -94,7 +126,7 @@ impl DailySchedule {
 
 impl std::cmp::PartialOrd for DailySchedule {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.time.cmp(&other.time))
+        Some(self.cmp(other))
     }
 }
 
@@ -106,7 +138,7 @@ impl std::cmp::Ord for DailySchedule {
 
 impl std::cmp::Eq for DailySchedule {}
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct DailyScheduleWithDate {
     pub date: String,
     pub schedules: Vec<DailySchedule>,
@@ -120,7 +152,7 @@ impl DailyScheduleWithDate {
 
 impl std::cmp::PartialOrd for DailyScheduleWithDate {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.date.cmp(&other.date))
+        Some(self.cmp(other))
     }
 }
 
@@ -132,6 +164,117 @@ impl std::cmp::Ord for DailyScheduleWithDate {
 
 impl std::cmp::Eq for DailyScheduleWithDate {} 
 
+/// A dose in the daily schedule of API v2.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ScheduledDose {
+    pub schedule_id: String,
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    /// The medicine, left out when it no longer exists
+    pub medicine: Option<Medicine>,
+    /// The schedule refers to a medicine that no longer exists
+    pub medicine_unresolved: bool,
+    pub amount: f64,
+    /// A dose of the medicine was logged for this time
+    pub taken: bool,
+}
+
+/// The doses to take at a time of day, API v2.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct DailyScheduleV2 {
+    pub time: String,
+    pub doses: Vec<ScheduledDose>,
+}
+
+/// The doses to take on a date by time of day, API v2.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct DailyScheduleWithDateV2 {
+    pub date: NaiveDate,
+    pub schedules: Vec<DailyScheduleV2>,
+}
+
+impl DailyScheduleWithDateV2 {
+    /// The daily schedule of every date from `from` up to and including `to`, the
+    /// schedules grouped by time. The doses of a medicine logged on a date in
+    /// `timezone` mark its scheduled times that day as taken, earliest first.
+    pub fn range(from: NaiveDate, to: NaiveDate, schedules: &[MedicineSchedule], medicines: &HashMap<MedicineId, Medicine>, history: &[DosageHistory], timezone: Tz) -> Vec<Self> {
+        let mut logged: HashMap<(NaiveDate, &str), usize> = HashMap::new();
+        for dose in history {
+            let date = dose.datetime.with_timezone(&timezone).date_naive();
+            if from <= date && date <= to {
+                *logged.entry((date, dose.medicine_id.as_str())).or_default() += 1;
+            }
+        }
+        
+        let mut schedules = schedules.to_vec();
+        schedules.sort();
+        from.iter_days()
+            .take_while(|date| *date <= to)
+            .map(|date| {
+                // The schedules are sorted by time of day, so each time is a run of them
+                let mut times: Vec<DailyScheduleV2> = Vec::new();
+                for group in schedules.chunk_by(|a, b| a.cmp(b).is_eq()) {
+                    let mut doses = Vec::new();
+                    for schedule in group {
+                        let taken = match logged.get_mut(&(date, schedule.medicine_id.as_str())) {
+                            Some(count) if *count > 0 => {
+                                *count -= 1;
+                                true
+                            }
+                            _ => false,
+                        };
+                        let medicine = medicines.get(&schedule.medicine_id).cloned();
+                        doses.push(ScheduledDose {
+                            schedule_id: schedule.id.clone(),
+                            medicine_id: schedule.medicine_id.clone(),
+                            medicine_unresolved: medicine.is_none(),
+                            medicine,
+                            amount: schedule.amount,
+                            taken,
+                        });
+                    }
+                    times.push(DailyScheduleV2 { time: group[0].time.clone(), doses });
+                }
+                Self { date, schedules: times }
+            })
+            .collect()
+    }
+}
+
+/// The v1 shape, a time counts as taken when all of its doses were.
+impl From<DailyScheduleWithDateV2> for DailyScheduleWithDate {
+    fn from(daily: DailyScheduleWithDateV2) -> Self {
+        let schedules = daily.schedules.into_iter()
+            .map(|schedule| DailySchedule {
+                time: schedule.time,
+                taken: Some(schedule.doses.iter().all(|dose| dose.taken)),
+                medicines: schedule.doses.into_iter().map(|dose| (dose.medicine, dose.amount)).collect(),
+            })
+            .collect();
+        Self::new(daily.date.to_string(), schedules)
+    }
+}
+
+/// Longest range of days the schedule can be asked for at once.
+pub const MAX_SCHEDULE_RANGE_DAYS: i64 = 92;
+
+/// The dates of a schedule range, both included.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScheduleRangeQuery {
+    /// First date as YYYY-MM-DD
+    pub from: NaiveDate,
+    /// Last date as YYYY-MM-DD
+    pub to: NaiveDate,
+}
+
+impl ScheduleRangeQuery {
+    /// `to` doesn't come before `from` and the range is at most `MAX_SCHEDULE_RANGE_DAYS` long.
+    pub fn is_valid(&self) -> bool {
+        self.from <= self.to && (self.to - self.from).num_days() < MAX_SCHEDULE_RANGE_DAYS
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,7 +317,7 @@ mod tests {
         let schedule2 = MedicineSchedule::new("12:00".to_string(), "med2".to_string(), 200.0);
         let schedule3 = MedicineSchedule::new("06:00".to_string(), "med3".to_string(), 300.0);
         
-        let mut schedules = vec![schedule1.clone(), schedule2.clone(), schedule3.clone()];
+        let mut schedules = [schedule1.clone(), schedule2.clone(), schedule3.clone()];
         schedules.sort();
         
         // Should be sorted by time (numerically)
@@ -218,6 +361,59 @@ mod tests {
         assert_eq!(schedule.description, "");
     }
 
+    #[test]
+    fn test_daily_usage() {
+        let schedules = vec![
+            MedicineSchedule::new("08:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::new("20:00".to_string(), "med1".to_string(), 2.0),
+            MedicineSchedule::new("08:00".to_string(), "med2".to_string(), 1.0),
+        ];
+
+        assert_eq!(daily_usage(&schedules, "med1"), 3.0);
+        assert_eq!(daily_usage(&schedules, "med2"), 1.0);
+        assert_eq!(daily_usage(&schedules, "med3"), 0.0);
+    }
+
+    #[test]
+    fn test_missed_doses() {
+        let schedules = vec![
+            MedicineSchedule::new("08:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::new("20:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::new("08:00".to_string(), "med2".to_string(), 1.0),
+        ];
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let dose = |day: u32, medicine_id: &str| DosageHistory::with_id(
+            "d".to_string(),
+            NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(8, 5, 0).unwrap().and_utc(),
+            medicine_id.to_string(),
+            1.0,
+        );
+        let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        let evening = NaiveTime::from_hms_opt(21, 0, 0).unwrap();
+
+        assert_eq!(missed_doses(&schedules, &[], date, noon, Tz::UTC), 2);
+        assert_eq!(missed_doses(&schedules, &[dose(15, "med1")], date, noon, Tz::UTC), 1);
+        assert_eq!(missed_doses(&schedules, &[dose(15, "med1")], date, evening, Tz::UTC), 2);
+        assert_eq!(missed_doses(&schedules, &[dose(14, "med1"), dose(15, "med2")], date, noon, Tz::UTC), 1);
+    }
+
+    #[test]
+    fn test_missed_doses_in_timezone() {
+        let schedules = vec![MedicineSchedule::new("00:15".to_string(), "med1".to_string(), 1.0)];
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        // 00:30 on the 15th in Amsterdam, still the 14th in UTC
+        let history = vec![DosageHistory::with_id(
+            "d".to_string(),
+            NaiveDate::from_ymd_opt(2024, 1, 14).unwrap().and_hms_opt(23, 30, 0).unwrap().and_utc(),
+            "med1".to_string(),
+            1.0,
+        )];
+        let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+
+        assert_eq!(missed_doses(&schedules, &history, date, noon, chrono_tz::Europe::Amsterdam), 0);
+        assert_eq!(missed_doses(&schedules, &history, date, noon, Tz::UTC), 1);
+    }
+
     #[test]
     fn test_daily_schedule_new() {
         let medicines = vec![
@@ -232,13 +428,124 @@ mod tests {
         assert_eq!(daily_schedule.taken, None);
     }
 
+    #[test]
+    fn test_daily_schedule_v2() {
+        let medicine = Medicine::with_id("med1".to_string(), "Paracetamol".to_string(), 500.0, "mg".to_string(), 20.0);
+        let medicines = HashMap::from([(medicine.id.clone(), medicine.clone())]);
+        let schedules = vec![
+            MedicineSchedule::with_id("s3".to_string(), "20:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::with_id("s1".to_string(), "08:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::with_id("s2".to_string(), "08:00".to_string(), "deleted".to_string(), 2.0),
+        ];
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let history = vec![
+            DosageHistory::with_id("d1".to_string(), date.and_hms_opt(9, 0, 0).unwrap().and_utc(), "med1".to_string(), 1.0),
+            DosageHistory::with_id("d2".to_string(), date.and_hms_opt(0, 0, 0).unwrap().and_utc() - chrono::Duration::hours(2), "med1".to_string(), 1.0),
+        ];
+        
+        let daily = DailyScheduleWithDateV2::range(date, date, &schedules, &medicines, &history, Tz::UTC).remove(0);
+        
+        assert_eq!(daily.schedules.len(), 2);
+        let morning = &daily.schedules[0];
+        assert_eq!(morning.time, "08:00");
+        assert_eq!(morning.doses[0].schedule_id, "s1");
+        assert_eq!(morning.doses[0].medicine, Some(medicine));
+        assert!(morning.doses[0].taken);
+        assert_eq!(morning.doses[1].medicine, None);
+        assert!(morning.doses[1].medicine_unresolved);
+        assert!(!morning.doses[1].taken);
+        // The dose logged the day before doesn't count
+        assert!(!daily.schedules[1].doses[0].taken);
+        
+        let json = serde_json::to_value(&daily).unwrap();
+        assert_eq!(json["date"], "2024-01-15");
+        assert_eq!(json["schedules"][0]["doses"][1]["medicine"], serde_json::Value::Null);
+        assert_eq!(json["schedules"][0]["doses"][1]["amount"], 2.0);
+    }
+
+    #[test]
+    fn test_daily_schedule_v2_orders_times_of_day() {
+        let schedules = vec![
+            MedicineSchedule::with_id("s1".to_string(), "20:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::with_id("s2".to_string(), "8:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::with_id("s3".to_string(), "08:00".to_string(), "med2".to_string(), 1.0),
+            MedicineSchedule::with_id("s4".to_string(), "12:30".to_string(), "med1".to_string(), 1.0),
+        ];
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        
+        let daily = DailyScheduleWithDateV2::range(date, date, &schedules, &HashMap::new(), &[], Tz::UTC).remove(0);
+        
+        let times: Vec<&str> = daily.schedules.iter().map(|time| time.time.as_str()).collect();
+        assert_eq!(times, ["8:00", "12:30", "20:00"]);
+        assert_eq!(daily.schedules[0].doses.len(), 2);
+    }
+
+    #[test]
+    fn test_daily_schedule_range() {
+        let medicine = Medicine::with_id("med1".to_string(), "Paracetamol".to_string(), 500.0, "mg".to_string(), 20.0);
+        let medicines = HashMap::from([(medicine.id.clone(), medicine.clone())]);
+        let schedules = vec![
+            MedicineSchedule::with_id("s1".to_string(), "08:00".to_string(), "med1".to_string(), 1.0),
+            MedicineSchedule::with_id("s2".to_string(), "20:00".to_string(), "med1".to_string(), 1.0),
+        ];
+        let date = |day: u32| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        let dose = |day: u32, hour: u32| DosageHistory::with_id(
+            "d".to_string(), date(day).and_hms_opt(hour, 0, 0).unwrap().and_utc(), "med1".to_string(), 1.0,
+        );
+        let history = vec![dose(14, 8), dose(15, 8), dose(15, 21), dose(16, 9)];
+        
+        let range = DailyScheduleWithDateV2::range(date(15), date(17), &schedules, &medicines, &history, Tz::UTC);
+        
+        assert_eq!(range.iter().map(|daily| daily.date).collect::<Vec<_>>(), vec![date(15), date(16), date(17)]);
+        let taken = |daily: &DailyScheduleWithDateV2| daily.schedules.iter().map(|schedule| schedule.doses[0].taken).collect::<Vec<_>>();
+        assert_eq!(taken(&range[0]), vec![true, true]);
+        assert_eq!(taken(&range[1]), vec![true, false]);
+        assert_eq!(taken(&range[2]), vec![false, false]);
+        assert!(DailyScheduleWithDateV2::range(date(17), date(15), &schedules, &medicines, &history, Tz::UTC).is_empty());
+        
+        let v1 = DailyScheduleWithDate::from(range[1].clone());
+        assert_eq!(v1.date, "2024-01-16");
+        assert_eq!(v1.schedules[0].medicines, vec![(Some(medicine), 1.0)]);
+        assert_eq!(v1.schedules[0].taken, Some(true));
+        assert_eq!(v1.schedules[1].taken, Some(false));
+    }
+
+    #[test]
+    fn test_daily_schedule_range_in_timezone() {
+        let medicines = HashMap::new();
+        let schedules = vec![MedicineSchedule::with_id("s1".to_string(), "08:00".to_string(), "med1".to_string(), 1.0)];
+        let date = |day: u32| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        // 00:30 on the 16th in Amsterdam, still the 15th in UTC
+        let history = vec![DosageHistory::with_id(
+            "d".to_string(), date(15).and_hms_opt(23, 30, 0).unwrap().and_utc(), "med1".to_string(), 1.0,
+        )];
+        
+        let taken = |timezone: Tz| DailyScheduleWithDateV2::range(date(15), date(16), &schedules, &medicines, &history, timezone)
+            .iter()
+            .map(|daily| daily.schedules[0].doses[0].taken)
+            .collect::<Vec<_>>();
+        
+        assert_eq!(taken(chrono_tz::Europe::Amsterdam), vec![false, true]);
+        assert_eq!(taken(Tz::UTC), vec![true, false]);
+    }
+
+    #[test]
+    fn test_schedule_range_query_is_valid() {
+        let query = |from: &str, to: &str| ScheduleRangeQuery { from: from.parse().unwrap(), to: to.parse().unwrap() };
+        
+        assert!(query("2024-01-15", "2024-01-15").is_valid());
+        assert!(query("2024-01-01", "2024-04-01").is_valid());
+        assert!(!query("2024-01-01", "2024-04-02").is_valid());
+        assert!(!query("2024-01-15", "2024-01-14").is_valid());
+    }
+
     #[test]
     fn test_daily_schedule_ordering() {
         let schedule1 = DailySchedule::new("08:00".to_string(), vec![]);
         let schedule2 = DailySchedule::new("12:00".to_string(), vec![]);
         let schedule3 = DailySchedule::new("06:00".to_string(), vec![]);
         
-        let mut schedules = vec![schedule1.clone(), schedule2.clone(), schedule3.clone()];
+        let mut schedules = [schedule1.clone(), schedule2.clone(), schedule3.clone()];
         schedules.sort();
         
         assert_eq!(schedules[0].time, "06:00");
@@ -265,7 +572,7 @@ mod tests {
         let schedule2 = DailyScheduleWithDate::new("2024-01-20".to_string(), vec![]);
         let schedule3 = DailyScheduleWithDate::new("2024-01-10".to_string(), vec![]);
         
-        let mut schedules = vec![schedule1.clone(), schedule2.clone(), schedule3.clone()];
+        let mut schedules = [schedule1.clone(), schedule2.clone(), schedule3.clone()];
         schedules.sort();
         
         assert_eq!(schedules[0].date, "2024-01-10");
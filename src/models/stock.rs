@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::NaiveDate;
+use uuid::Uuid;
+use crate::models::medicine::MedicineId;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct StockBatch {
+    pub id: String,
+    pub quantity: f64,
+    pub lot: Option<String>,
+    pub expiry_date: Option<NaiveDate>,
+    pub received_date: NaiveDate,
+}
+
+impl StockBatch {
+    pub fn new(quantity: f64, lot: Option<String>, expiry_date: Option<NaiveDate>, received_date: NaiveDate) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            quantity,
+            lot,
+            expiry_date,
+            received_date,
+        }
+    }
+}
+
+// Batches are ordered first-expiry-first-out: dated batches by expiry date, then
+// batches without an expiry date, ties broken by the date they were received.
+impl std::cmp::PartialOrd for StockBatch {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::cmp::Ord for StockBatch {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let expiry = match (self.expiry_date, other.expiry_date) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        };
+        expiry.then_with(|| self.received_date.cmp(&other.received_date))
+    }
+}
+
+impl std::cmp::Eq for StockBatch {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ExpiringStock {
+    #[schema(value_type = String)]
+    pub medicine_id: MedicineId,
+    pub medicine_name: String,
+    #[serde(flatten)]
+    pub batch: StockBatch,
+}
+
+/// Longest period accepted, about ten years.
+pub const MAX_PERIOD_DAYS: i64 = 3650;
+
+/// Parses a period like `30d`, `2w` or a bare number of days into a number of days,
+/// of at most `MAX_PERIOD_DAYS`.
+pub fn parse_period_days(period: &str) -> Option<i64> {
+    let period = period.trim();
+    let (number, multiplier) = match period.chars().last()? {
+        'd' => (&period[..period.len() - 1], 1),
+        'w' => (&period[..period.len() - 1], 7),
+        _ => (period, 1),
+    };
+    number.parse::<i64>().ok()
+        .filter(|days| *days >= 0)
+        .and_then(|days| days.checked_mul(multiplier))
+        .filter(|days| *days <= MAX_PERIOD_DAYS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_stock_batch_new() {
+        let batch = StockBatch::new(30.0, Some("LOT-1".to_string()), Some(date(2025, 6, 1)), date(2024, 1, 1));
+
+        assert!(!batch.id.is_empty());
+        assert_eq!(batch.quantity, 30.0);
+        assert_eq!(batch.lot, Some("LOT-1".to_string()));
+        assert_eq!(batch.expiry_date, Some(date(2025, 6, 1)));
+        assert_eq!(batch.received_date, date(2024, 1, 1));
+    }
+
+    #[test]
+    fn test_stock_batch_ordering_is_first_expiry_first_out() {
+        let late = StockBatch::new(10.0, None, Some(date(2025, 1, 1)), date(2024, 1, 1));
+        let early = StockBatch::new(10.0, None, Some(date(2024, 6, 1)), date(2024, 2, 1));
+        let undated = StockBatch::new(10.0, None, None, date(2023, 1, 1));
+
+        let mut batches = [undated.clone(), late.clone(), early.clone()];
+        batches.sort();
+
+        assert_eq!(batches[0].id, early.id);
+        assert_eq!(batches[1].id, late.id);
+        assert_eq!(batches[2].id, undated.id);
+    }
+
+    #[test]
+    fn test_parse_period_days() {
+        assert_eq!(parse_period_days("30d"), Some(30));
+        assert_eq!(parse_period_days("2w"), Some(14));
+        assert_eq!(parse_period_days("45"), Some(45));
+        assert_eq!(parse_period_days("-3d"), None);
+        assert_eq!(parse_period_days("soon"), None);
+        assert_eq!(parse_period_days(""), None);
+        assert_eq!(parse_period_days("3650d"), Some(MAX_PERIOD_DAYS));
+        assert_eq!(parse_period_days("100000000d"), None);
+        assert_eq!(parse_period_days("2000000000000000000w"), None);
+    }
+
+    #[test]
+    fn test_expiring_stock_serialization() {
+        let expiring = ExpiringStock {
+            medicine_id: "medicine-id".to_string(),
+            medicine_name: "Aspirin".to_string(),
+            batch: StockBatch::new(20.0, Some("A1".to_string()), Some(date(2024, 5, 1)), date(2024, 1, 1)),
+        };
+
+        let json = serde_json::to_value(&expiring).unwrap();
+        assert_eq!(json["medicine_name"], "Aspirin");
+        assert_eq!(json["lot"], "A1");
+        assert_eq!(json["expiry_date"], "2024-05-01");
+
+        let deserialized: ExpiringStock = serde_json::from_value(json).unwrap();
+        assert_eq!(expiring, deserialized);
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Warning {
+    pub code: String,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(code: &str, message: String) -> Self {
+        Self {
+            code: code.to_string(),
+            message,
+        }
+    }
+}
+
+/// A response item with the warnings raised while handling it. The warnings are
+/// only serialized when there are any, so the item keeps its usual shape.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct WithWarnings<T> {
+    #[serde(flatten)]
+    pub item: T,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+}
+
+impl<T> WithWarnings<T> {
+    pub fn new(item: T, warnings: Vec<Warning>) -> Self {
+        Self { item, warnings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Medicine;
+
+    #[test]
+    fn test_with_warnings_keeps_item_shape() {
+        let medicine = Medicine::with_id("id-1".to_string(), "Aspirin".to_string(), 500.0, "mg".to_string(), 10.0);
+
+        let without = serde_json::to_value(WithWarnings::new(medicine.clone(), vec![])).unwrap();
+        assert_eq!(without, serde_json::to_value(&medicine).unwrap());
+
+        let with = serde_json::to_value(WithWarnings::new(medicine, vec![Warning::new("test", "Test warning".to_string())])).unwrap();
+        assert_eq!(with["name"], "Aspirin");
+        assert_eq!(with["warnings"][0]["code"], "test");
+        assert_eq!(with["warnings"][0]["message"], "Test warning");
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use redis::{AsyncCommands, ExistenceCheck, SetOptions};
+use serde_json;
+use crate::models::ApiKey;
+use crate::repositories::{InstrumentedConnection, RedisConnection};
+
+pub struct ApiKeyRepository {
+    redis: RedisConnection,
+    prefix: String,
+}
+
+impl ApiKeyRepository {
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
+            prefix,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("api_key").await
+    }
+
+    pub async fn get_by_subject(&self, subject: &str) -> Result<Vec<ApiKey>> {
+        let mut conn = self.get_connection().await?;
+        let pattern = format!("{}*", self.prefix);
+        let keys: Vec<String> = conn.keys(&pattern).await?;
+
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        let mut api_keys = Vec::new();
+
+        for json_str in values.into_iter().flatten() {
+            if let Ok(api_key) = serde_json::from_str::<ApiKey>(&json_str) {
+                if api_key.subject == subject {
+                    api_keys.push(api_key);
+                }
+            }
+        }
+
+        api_keys.sort_by_key(|api_key| api_key.created_at);
+        Ok(api_keys)
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> Result<Option<ApiKey>> {
+        let key = format!("{}{}", self.prefix, id);
+        let mut conn = self.get_connection().await?;
+        let value: Option<String> = conn.get(&key).await?;
+
+        match value {
+            Some(json_str) => {
+                let api_key = serde_json::from_str::<ApiKey>(&json_str)?;
+                Ok(Some(api_key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save(&self, api_key: &ApiKey) -> Result<()> {
+        let key = format!("{}{}", self.prefix, api_key.id);
+        let value = serde_json::to_string(api_key)?;
+
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.set(&key, value).await?;
+
+        Ok(())
+    }
+
+    /// Records that the key was used at `now`. Nothing is written when the key is
+    /// revoked meanwhile, and no other change to it is overwritten.
+    pub async fn touch(&self, id: &str, now: DateTime<Utc>) -> Result<()> {
+        let key = format!("{}{}", self.prefix, id);
+        let key = &key;
+
+        self.redis.watched("api_key", std::slice::from_ref(key), || async move {
+            let mut pipe = redis::pipe();
+            if let Some(api_key) = self.get_by_id(id).await? {
+                let value = serde_json::to_string(&ApiKey { last_used_at: Some(now), ..api_key })?;
+                pipe.set_options(key, value, SetOptions::default().conditional_set(ExistenceCheck::XX)).ignore();
+            }
+            Ok((pipe, ()))
+        }).await
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        let key = format!("{}{}", self.prefix, id);
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.del(&key).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn create_test_repository() -> ApiKeyRepository {
+        let redis = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+        ApiKeyRepository::new(redis, "test:apikey:".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_touch_records_last_use() {
+        let repo = create_test_repository();
+        let now = Utc::now();
+        let (api_key, _) = ApiKey::generate("Scale".to_string(), "user-1".to_string(), vec!["medicine:read".to_string()], None, now);
+        repo.save(&api_key).await.unwrap();
+
+        repo.touch(&api_key.id, now).await.unwrap();
+
+        let touched = repo.get_by_id(&api_key.id).await.unwrap().unwrap();
+        assert_eq!(touched.last_used_at, Some(now));
+        assert_eq!(touched.scopes, api_key.scopes);
+        repo.delete(&api_key.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_touch_does_not_restore_a_revoked_key() {
+        let repo = create_test_repository();
+        let (api_key, _) = ApiKey::generate("Scale".to_string(), "user-1".to_string(), vec![], None, Utc::now());
+        repo.save(&api_key).await.unwrap();
+        repo.delete(&api_key.id).await.unwrap();
+
+        repo.touch(&api_key.id, Utc::now()).await.unwrap();
+
+        assert!(repo.get_by_id(&api_key.id).await.unwrap().is_none());
+    }
+}
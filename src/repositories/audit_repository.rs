@@ -0,0 +1,113 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use redis::{AsyncCommands, Pipeline};
+use serde::Serialize;
+use serde_json;
+use crate::models::{AuditAction, AuditEntry, ProfileId};
+use crate::repositories::{InstrumentedConnection, RedisConnection};
+
+/// Append-only log of changes, kept in a sorted set scored by timestamp.
+#[derive(Clone)]
+pub struct AuditRepository {
+    redis: RedisConnection,
+    key: String,
+}
+
+impl AuditRepository {
+    pub fn new(redis: RedisConnection, key: String) -> Self {
+        Self {
+            redis,
+            key,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("audit").await
+    }
+
+    /// Appends the entry as part of `pipe`, so it is stored together with the change
+    /// it records.
+    pub fn append_to(&self, pipe: &mut Pipeline, entry: &AuditEntry) -> Result<()> {
+        let value = serde_json::to_string(entry)?;
+        pipe.zadd(&self.key, value, entry.timestamp.timestamp_millis()).ignore();
+
+        Ok(())
+    }
+
+    /// Entries from `from` up to, but not including, `to`, oldest first.
+    pub async fn get_range(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Vec<AuditEntry>> {
+        let min = from.map(|from| from.timestamp_millis().to_string()).unwrap_or_else(|| "-inf".to_string());
+        let max = to.map(|to| format!("({}", to.timestamp_millis())).unwrap_or_else(|| "+inf".to_string());
+
+        let mut conn = self.get_connection().await?;
+        let values: Vec<String> = conn.zrangebyscore(&self.key, min, max).await?;
+
+        Ok(values.iter()
+            .filter_map(|json_str| serde_json::from_str::<AuditEntry>(json_str).ok())
+            .collect())
+    }
+}
+
+/// Records the changes made by one user to one profile.
+#[derive(Clone)]
+pub struct Auditor {
+    repo: AuditRepository,
+    actor: String,
+    profile_id: ProfileId,
+}
+
+impl Auditor {
+    pub fn new(repo: AuditRepository, actor: String, profile_id: ProfileId) -> Self {
+        Self { repo, actor, profile_id }
+    }
+
+    /// Records a change as part of `pipe`, which should be atomic and also make the change.
+    pub fn record<T: Serialize>(&self, pipe: &mut Pipeline, entity: &str, id: &str, action: AuditAction, before: Option<&T>, after: Option<&T>) -> Result<()> {
+        let entry = AuditEntry::new(
+            self.actor.clone(),
+            self.profile_id.clone(),
+            entity,
+            id.to_string(),
+            action,
+            before.map(serde_json::to_value).transpose()?,
+            after.map(serde_json::to_value).transpose()?,
+        );
+        self.repo.append_to(pipe, &entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_record_is_part_of_the_transaction() {
+        let redis = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+        let auditor = Auditor::new(AuditRepository::new(redis, "test:audit".to_string()), "user-1".to_string(), "default".to_string());
+        let mut pipe = redis::pipe();
+        pipe.atomic().set("test:schedule:s1", "{}").ignore();
+
+        auditor.record(&mut pipe, "schedule", "s1", AuditAction::Create, None, Some(&serde_json::json!({}))).unwrap();
+
+        let packed = String::from_utf8_lossy(&pipe.get_packed_pipeline()).to_string();
+        let position = |command: &str| packed.find(&format!("\r\n{}\r\n", command)).unwrap();
+        assert!(position("MULTI") < position("SET"));
+        assert!(position("SET") < position("ZADD"));
+        assert!(position("ZADD") < position("EXEC"));
+        assert!(packed.contains("test:audit"));
+    }
+
+    #[test]
+    fn test_each_profile_has_a_log_of_its_own() {
+        let redis = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+        let repos = crate::repositories::Repositories::new(&redis, "test:profile-1:");
+        let auditor = Auditor::new(repos.audit_repo.clone(), "user-1".to_string(), "profile-1".to_string());
+        let mut pipe = redis::pipe();
+
+        auditor.record(&mut pipe, "schedule", "s1", AuditAction::Delete, Some(&serde_json::json!({})), None).unwrap();
+
+        let packed = String::from_utf8_lossy(&pipe.get_packed_pipeline()).to_string();
+        assert!(packed.contains("\r\ntest:profile-1:audit\r\n"));
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use redis::AsyncCommands;
+use serde_json;
+use crate::models::{AuditAction, CalendarSubscription};
+use crate::repositories::{Auditor, InstrumentedConnection, RedisConnection};
+
+pub struct CalendarSubscriptionRepository {
+    redis: RedisConnection,
+    prefix: String,
+}
+
+impl CalendarSubscriptionRepository {
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
+            prefix,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("calendar").await
+    }
+
+    pub async fn get_by_profile(&self, profile_id: &str) -> Result<Option<CalendarSubscription>> {
+        let key = format!("{}{}", self.prefix, profile_id);
+        let mut conn = self.get_connection().await?;
+        let value: Option<String> = conn.get(&key).await?;
+
+        match value {
+            Some(json_str) => {
+                let subscription = serde_json::from_str::<CalendarSubscription>(&json_str)?;
+                Ok(Some(subscription))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Saves the subscription, replacing the one the profile had, and records it with
+    /// `auditor`.
+    pub async fn save(&self, subscription: &CalendarSubscription, auditor: &Auditor) -> Result<()> {
+        let key = format!("{}{}", self.prefix, subscription.profile_id);
+        let value = serde_json::to_string(subscription)?;
+        let existing = self.get_by_profile(&subscription.profile_id).await?;
+        let action = if existing.is_some() { AuditAction::Update } else { AuditAction::Create };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().set(&key, value).ignore();
+        auditor.record(&mut pipe, "calendar_subscription", &subscription.profile_id, action, existing.as_ref(), Some(subscription))?;
+
+        let mut conn = self.get_connection().await?;
+        let _: () = pipe.query_async(&mut conn).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, profile_id: &str, auditor: &Auditor) -> Result<()> {
+        let key = format!("{}{}", self.prefix, profile_id);
+        let Some(existing) = self.get_by_profile(profile_id).await? else {
+            return Ok(());
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        auditor.record(&mut pipe, "calendar_subscription", profile_id, AuditAction::Delete, Some(&existing), None)?;
+
+        let mut conn = self.get_connection().await?;
+        let _: () = pipe.query_async(&mut conn).await?;
+
+        Ok(())
+    }
+}
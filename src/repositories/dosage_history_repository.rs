@@ -1,27 +1,44 @@
 use anyhow::Result;
-use redis::{AsyncCommands, Client, aio::Connection};
+use redis::{AsyncCommands, Pipeline};
 use serde_json;
-use crate::models::{DosageHistory, ApiDosageHistory};
+use crate::models::{DosageHistory, ApiDosageHistory, AuditAction};
+use crate::repositories::{Auditor, InstrumentedConnection, RedisConnection};
 
 pub struct DosageHistoryRepository {
-    redis_client: Client,
+    redis: RedisConnection,
     prefix: String,
+    auditor: Option<Auditor>,
 }
 
 impl DosageHistoryRepository {
-    pub fn new(redis_url: &str, prefix: String) -> Result<Self> {
-        let client = Client::open(redis_url)?;
-        Ok(Self {
-            redis_client: client,
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
             prefix,
-        })
+            auditor: None,
+        }
+    }
+
+    pub fn with_auditor(self, auditor: Auditor) -> Self {
+        Self {
+            auditor: Some(auditor),
+            ..self
+        }
+    }
+
+    fn audit(&self, pipe: &mut Pipeline, id: &str, action: AuditAction, before: Option<&DosageHistory>, after: Option<&DosageHistory>) -> Result<()> {
+        match &self.auditor {
+            Some(auditor) => auditor.record(pipe, "dosage", id, action, before, after),
+            None => Ok(()),
+        }
     }
 
-    async fn get_connection(&self) -> Result<Connection> {
-        self.redis_client.get_async_connection().await.map_err(|e| anyhow::anyhow!(e))
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("dosage").await
     }
 
-    pub async fn create(&self, api_history: ApiDosageHistory) -> Result<String> {
+    /// The dose and the writes storing it, for a transaction that also takes it out of stock.
+    pub fn prepare_create(&self, api_history: ApiDosageHistory) -> Result<(DosageHistory, Pipeline)> {
         let history = api_history.to_dosage_history(
             uuid::Uuid::new_v4().to_string(),
             String::new()
@@ -29,10 +46,11 @@ impl DosageHistoryRepository {
         let key = format!("{}{}", self.prefix, history.id);
         let value = serde_json::to_string(&history)?;
         
-        let mut conn = self.get_connection().await?;
-        let _: () = conn.set(&key, value).await?;
+        let mut pipe = redis::pipe();
+        pipe.set(&key, value).ignore();
+        self.audit(&mut pipe, &history.id, AuditAction::Create, None, Some(&history))?;
         
-        Ok(history.id)
+        Ok((history, pipe))
     }
 
     pub async fn get_all(&self) -> Result<Vec<DosageHistory>> {
@@ -47,11 +65,9 @@ impl DosageHistoryRepository {
         let values: Vec<Option<String>> = conn.mget(&keys).await?;
         let mut histories = Vec::new();
         
-        for value in values {
-            if let Some(json_str) = value {
-                if let Ok(history) = serde_json::from_str::<DosageHistory>(&json_str) {
-                    histories.push(history);
-                }
+        for json_str in values.into_iter().flatten() {
+            if let Ok(history) = serde_json::from_str::<DosageHistory>(&json_str) {
+                histories.push(history);
             }
         }
         
@@ -59,6 +75,14 @@ impl DosageHistoryRepository {
         Ok(histories)
     }
 
+    pub async fn get_by_medicine_id(&self, medicine_id: &str) -> Result<Vec<DosageHistory>> {
+        let histories = self.get_all().await?
+            .into_iter()
+            .filter(|history| history.medicine_id == medicine_id)
+            .collect();
+        Ok(histories)
+    }
+
     pub async fn get_by_id(&self, id: &str) -> Result<Option<DosageHistory>> {
         let key = format!("{}{}", self.prefix, id);
         let mut conn = self.get_connection().await?;
@@ -75,8 +99,13 @@ impl DosageHistoryRepository {
 
     pub async fn delete(&self, id: &str) -> Result<()> {
         let key = format!("{}{}", self.prefix, id);
+        let existing = self.get_by_id(id).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        self.audit(&mut pipe, id, AuditAction::Delete, existing.as_ref(), None)?;
+        
         let mut conn = self.get_connection().await?;
-        let _: () = conn.del(&key).await?;
+        let _: () = pipe.query_async(&mut conn).await?;
         
         Ok(())
     }
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use redis::AsyncCommands;
+use serde_json;
+use crate::models::Grant;
+use crate::repositories::{InstrumentedConnection, RedisConnection};
+
+/// Grants are stored per profile, under `{prefix}{profile_id}:{subject}`.
+pub struct GrantRepository {
+    redis: RedisConnection,
+    prefix: String,
+}
+
+impl GrantRepository {
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
+            prefix,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("grant").await
+    }
+
+    fn key(&self, profile_id: &str, subject: &str) -> String {
+        format!("{}{}:{}", self.prefix, profile_id, subject)
+    }
+
+    pub async fn get_by_profile(&self, profile_id: &str) -> Result<Vec<Grant>> {
+        let mut conn = self.get_connection().await?;
+        let pattern = format!("{}{}:*", self.prefix, profile_id);
+        let keys: Vec<String> = conn.keys(&pattern).await?;
+
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        let mut grants = Vec::new();
+
+        for json_str in values.into_iter().flatten() {
+            if let Ok(grant) = serde_json::from_str::<Grant>(&json_str) {
+                grants.push(grant);
+            }
+        }
+
+        grants.sort_by(|a, b| a.subject.cmp(&b.subject));
+        Ok(grants)
+    }
+
+    pub async fn get(&self, profile_id: &str, subject: &str) -> Result<Option<Grant>> {
+        let key = self.key(profile_id, subject);
+        let mut conn = self.get_connection().await?;
+        let value: Option<String> = conn.get(&key).await?;
+
+        match value {
+            Some(json_str) => {
+                let grant = serde_json::from_str::<Grant>(&json_str)?;
+                Ok(Some(grant))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save(&self, grant: &Grant) -> Result<()> {
+        let key = self.key(&grant.profile_id, &grant.subject);
+        let value = serde_json::to_string(grant)?;
+
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.set(&key, value).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, profile_id: &str, subject: &str) -> Result<()> {
+        let key = self.key(profile_id, subject);
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.del(&key).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_by_profile(&self, profile_id: &str) -> Result<()> {
+        let mut conn = self.get_connection().await?;
+        let keys: Vec<String> = conn.keys(format!("{}{}:*", self.prefix, profile_id)).await?;
+        if !keys.is_empty() {
+            let _: () = conn.del(&keys).await?;
+        }
+
+        Ok(())
+    }
+}
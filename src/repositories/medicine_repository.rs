@@ -1,33 +1,137 @@
 use anyhow::Result;
-use redis::{AsyncCommands, Client, aio::Connection};
+use redis::{AsyncCommands, Pipeline};
 use serde_json;
-use crate::models::{Medicine, ApiMedicine, MedicineId};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::future::Future;
+use crate::models::{
+    Medicine, ApiMedicine, MedicineId, StockBatch, ExpiringStock, AuditAction, LedgerEntry,
+    Reconciliation, StockMovementKind, ledger_balance
+};
+use crate::repositories::{Auditor, StockLedgerRepository, InstrumentedConnection, RedisConnection};
+
+/// No writes along with a change of stock.
+const NOTHING_ALONG: Option<Pipeline> = None;
+
+/// Writes stored in the same transaction as a change of stock, e.g. the dose that
+/// uses it up. They are prepared after `keys` are watched, so what they read can't
+/// change before they're stored.
+pub trait AlongWithStock: Sync {
+    fn keys(&self) -> Vec<String>;
+
+    fn prepare(&self, pipe: &mut Pipeline) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl AlongWithStock for Pipeline {
+    fn keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    async fn prepare(&self, pipe: &mut Pipeline) -> Result<()> {
+        for cmd in self.cmd_iter() {
+            pipe.add_command(cmd.clone()).ignore();
+        }
+        Ok(())
+    }
+}
+
+impl<A: AlongWithStock> AlongWithStock for Option<A> {
+    fn keys(&self) -> Vec<String> {
+        self.iter().flat_map(|along| along.keys()).collect()
+    }
+
+    async fn prepare(&self, pipe: &mut Pipeline) -> Result<()> {
+        match self {
+            Some(along) => along.prepare(pipe).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// How a change of stock is booked in the ledger and the audit log.
+struct Movement {
+    kind: StockMovementKind,
+    action: AuditAction,
+    reason: Option<String>,
+    batch_id: Option<String>,
+}
+
+impl Movement {
+    fn new(kind: StockMovementKind, action: AuditAction) -> Self {
+        Self {
+            kind,
+            action,
+            reason: None,
+            batch_id: None,
+        }
+    }
+
+    fn with_reason(self, reason: Option<String>) -> Self {
+        Self { reason, ..self }
+    }
+
+    fn with_batch(self, batch_id: &str) -> Self {
+        Self { batch_id: Some(batch_id.to_string()), ..self }
+    }
+}
 
 pub struct MedicineRepository {
-    redis_client: Client,
+    redis: RedisConnection,
     prefix: String,
+    auditor: Option<Auditor>,
+    ledger: Option<StockLedgerRepository>,
 }
 
 impl MedicineRepository {
-    pub fn new(redis_url: &str, prefix: String) -> Result<Self> {
-        let client = Client::open(redis_url)?;
-        Ok(Self {
-            redis_client: client,
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
             prefix,
-        })
+            auditor: None,
+            ledger: None,
+        }
     }
 
-    async fn get_connection(&self) -> Result<Connection> {
-        self.redis_client.get_async_connection().await.map_err(|e| anyhow::anyhow!(e))
+    /// Books every change of stock in `ledger`, the stored stock follows its balance.
+    pub fn with_ledger(self, ledger: StockLedgerRepository) -> Self {
+        Self {
+            ledger: Some(ledger),
+            ..self
+        }
+    }
+
+    pub fn with_auditor(self, auditor: Auditor) -> Self {
+        Self {
+            auditor: Some(auditor),
+            ..self
+        }
+    }
+
+    fn audit(&self, pipe: &mut Pipeline, id: &str, action: AuditAction, before: Option<&Medicine>, after: Option<&Medicine>) -> Result<()> {
+        match &self.auditor {
+            Some(auditor) => auditor.record(pipe, "medicine", id, action, before, after),
+            None => Ok(()),
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("medicine").await
     }
 
     pub async fn create(&self, api_medicine: ApiMedicine) -> Result<MedicineId> {
         let medicine = api_medicine.to_medicine();
-        let key = format!("{}{}", self.prefix, medicine.id);
-        let value = serde_json::to_string(&medicine)?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.save_in(&mut pipe, &medicine)?;
+        self.audit(&mut pipe, &medicine.id, AuditAction::Create, None, Some(&medicine))?;
         
-        let mut conn = self.get_connection().await?;
-        let _: () = conn.set(&key, value).await?;
+        // Starts the ledger with the initial stock
+        if let Some(ledger) = &self.ledger {
+            if medicine.stock != 0.0 {
+                ledger.append_to(&mut pipe, &LedgerEntry::new(medicine.id.clone(), StockMovementKind::Opening, medicine.stock, medicine.stock, None, None))?;
+            }
+        }
+        self.execute(&pipe).await?;
         
         Ok(medicine.id)
     }
@@ -44,11 +148,9 @@ impl MedicineRepository {
         let values: Vec<Option<String>> = conn.mget(&keys).await?;
         let mut medicines = Vec::new();
         
-        for value in values {
-            if let Some(json_str) = value {
-                if let Ok(medicine) = serde_json::from_str::<Medicine>(&json_str) {
-                    medicines.push(medicine);
-                }
+        for json_str in values.into_iter().flatten() {
+            if let Ok(medicine) = serde_json::from_str::<Medicine>(&json_str) {
+                medicines.push(medicine);
             }
         }
         
@@ -70,72 +172,226 @@ impl MedicineRepository {
         }
     }
 
-    pub async fn update(&self, id: &str, api_medicine: ApiMedicine) -> Result<bool> {
-        let medicine = api_medicine.to_medicine_with_id(id.to_string());
-        let key = format!("{}{}", self.prefix, id);
-        let value = serde_json::to_string(&medicine)?;
+    /// The medicines with the given ids by id, fetched in a single MGET. Ids of
+    /// medicines that don't exist are left out.
+    pub async fn get_many(&self, ids: &[MedicineId]) -> Result<HashMap<MedicineId, Medicine>> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
         
+        let keys: Vec<String> = ids.iter().map(|id| format!("{}{}", self.prefix, id)).collect();
         let mut conn = self.get_connection().await?;
-        let _: () = conn.set(&key, value).await?;
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        
+        let mut medicines = HashMap::new();
+        for json_str in values.into_iter().flatten() {
+            let medicine = serde_json::from_str::<Medicine>(&json_str)?;
+            medicines.insert(medicine.id.clone(), medicine);
+        }
+        Ok(medicines)
+    }
+
+    pub async fn update(&self, id: &str, api_medicine: ApiMedicine) -> Result<bool> {
+        let medicine = api_medicine.to_medicine_with_id(id.to_string());
+        // A changed stock is booked as a correction
+        let reason = Some("Stock changed by an update".to_string());
+        let movement = Movement::new(StockMovementKind::Correction, AuditAction::Update).with_reason(reason);
+        let moved = self.apply_movement(id, movement, &NOTHING_ALONG, |existing| {
+            medicine.with_batches_of(existing)
+        }).await?;
+        if moved.is_none() {
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            self.save_in(&mut pipe, &medicine)?;
+            self.audit(&mut pipe, id, AuditAction::Update, None, Some(&medicine))?;
+            self.execute(&pipe).await?;
+        }
         
         Ok(true)
     }
 
     pub async fn delete(&self, id: &str) -> Result<()> {
         let key = format!("{}{}", self.prefix, id);
-        let mut conn = self.get_connection().await?;
-        let _: () = conn.del(&key).await?;
+        let existing = self.get_by_id(id).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        self.audit(&mut pipe, id, AuditAction::Delete, existing.as_ref(), None)?;
+        if let Some(ledger) = &self.ledger {
+            ledger.delete_in(&mut pipe, id);
+        }
+        self.execute(&pipe).await?;
         
         Ok(())
     }
 
-    pub async fn add_stock(&self, id: &str, amount: f64) -> Result<bool> {
-        if let Some(medicine) = self.get_by_id(id).await? {
-            let updated_medicine = medicine.add_stock(amount);
-            let key = format!("{}{}", self.prefix, id);
-            let value = serde_json::to_string(&updated_medicine)?;
+    /// Adds a batch to the stock, storing the writes of `along`, e.g. the refill of a
+    /// prescription it fills, in the same transaction.
+    pub async fn add_stock(&self, id: &str, batch: StockBatch, along: &impl AlongWithStock) -> Result<bool> {
+        let movement = Movement::new(StockMovementKind::Refill, AuditAction::AddStock).with_batch(&batch.id);
+        let moved = self.apply_movement(id, movement, along, |medicine| {
+            medicine.add_stock(batch.clone())
+        }).await?;
+        
+        Ok(moved.is_some())
+    }
+
+    /// Takes a dose out of stock, storing the writes of `along`, e.g. the dose itself,
+    /// in the same transaction.
+    pub async fn consume_stock(&self, id: &str, amount: f64, along: &impl AlongWithStock) -> Result<bool> {
+        let movement = Movement::new(StockMovementKind::DoseConsumed, AuditAction::ConsumeStock);
+        let moved = self.apply_movement(id, movement, along, |medicine| {
+            medicine.consume_stock(amount)
+        }).await?;
+        
+        Ok(moved.is_some())
+    }
+
+    /// Books the difference between a physical count and the stock as a correction.
+    pub async fn reconcile(&self, id: &str, counted: f64, reason: Option<String>) -> Result<Option<Reconciliation>> {
+        let movement = Movement::new(StockMovementKind::Correction, AuditAction::CorrectStock).with_reason(reason);
+        let moved = self.apply_movement(id, movement, &NOTHING_ALONG, |medicine| {
+            medicine.adjust_stock(counted - medicine.stock)
+        }).await?;
+        
+        Ok(moved.map(|(medicine, _, correction)| Reconciliation {
+            medicine_id: id.to_string(),
+            expected: medicine.stock,
+            counted,
+            difference: counted - medicine.stock,
+            correction,
+        }))
+    }
+
+    /// Takes the batches that expired before `today` out of stock, one disposal per batch.
+    pub async fn dispose_expired(&self, id: &str, today: NaiveDate) -> Result<Option<Vec<LedgerEntry>>> {
+        let medicine = match self.get_by_id(id).await? {
+            Some(medicine) => medicine,
+            None => return Ok(None),
+        };
+        let mut entries = Vec::new();
+        for batch in medicine.expired_batches(today) {
+            let reason = batch.expiry_date.map(|expiry| format!("Expired on {}", expiry));
+            let movement = Movement::new(StockMovementKind::Disposal, AuditAction::DisposeStock).with_reason(reason).with_batch(&batch.id);
+            let moved = self.apply_movement(id, movement, &NOTHING_ALONG, |medicine| {
+                medicine.remove_batch(&batch.id)
+            }).await?;
+            entries.extend(moved.and_then(|(_, _, entry)| entry));
+        }
+        
+        Ok(Some(entries))
+    }
+
+    pub async fn get_ledger(&self, id: &str) -> Result<Vec<LedgerEntry>> {
+        match &self.ledger {
+            Some(ledger) => ledger.get_by_medicine_id(id).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn get_expiring(&self, before: NaiveDate) -> Result<Vec<ExpiringStock>> {
+        let mut expiring: Vec<ExpiringStock> = self.get_all().await?
+            .iter()
+            .flat_map(|medicine| medicine.expiring_before(before))
+            .collect();
+        expiring.sort_by(|a, b| a.batch.cmp(&b.batch));
+        Ok(expiring)
+    }
+
+    /// Changes the stock of a medicine with `change` and books the difference in the
+    /// ledger, together with the writes of `along`, in one transaction. It is retried
+    /// when the medicine or its ledger change meanwhile, so no movement gets lost. The
+    /// stored stock is the ledger balance after the movement.
+    ///
+    /// Returns the medicine before and after the movement, or nothing when it doesn't exist.
+    async fn apply_movement<A, F>(
+        &self,
+        id: &str,
+        movement: Movement,
+        along: &A,
+        change: F,
+    ) -> Result<Option<(Medicine, Medicine, Option<LedgerEntry>)>>
+    where
+        A: AlongWithStock,
+        F: Fn(&Medicine) -> Medicine,
+    {
+        let mut keys = vec![format!("{}{}", self.prefix, id)];
+        keys.extend(along.keys());
+        if let Some(ledger) = &self.ledger {
+            keys.push(ledger.key(id));
+        }
+        let (movement, change) = (&movement, &change);
+        
+        self.redis.watched("medicine", &keys, || async move {
+            let mut pipe = redis::pipe();
+            let medicine = match self.get_by_id(id).await? {
+                Some(medicine) => medicine,
+                None => return Ok((pipe, None)),
+            };
+            let updated = change(&medicine);
+            let quantity = updated.stock - medicine.stock;
+            let (updated, entry) = match &self.ledger {
+                Some(ledger) if quantity != 0.0 => {
+                    let mut entries = ledger.get_by_medicine_id(id).await?;
+                    if entries.is_empty() && medicine.stock != 0.0 {
+                        // Stock recorded before the ledger existed
+                        let opening = LedgerEntry::new(medicine.id.clone(), StockMovementKind::Opening, medicine.stock, medicine.stock, None, None);
+                        ledger.append_to(&mut pipe, &opening)?;
+                        entries.push(opening);
+                    }
+                    let balance = ledger_balance(&entries) + quantity;
+                    let entry = LedgerEntry::new(medicine.id.clone(), movement.kind, quantity, balance, movement.reason.clone(), movement.batch_id.clone());
+                    ledger.append_to(&mut pipe, &entry)?;
+                    (Medicine { stock: balance, ..updated }, Some(entry))
+                }
+                _ => (updated, None),
+            };
             
-            let mut conn = self.get_connection().await?;
-            let _: () = conn.set(&key, value).await?;
+            self.save_in(&mut pipe, &updated)?;
+            self.audit(&mut pipe, id, movement.action, Some(&medicine), Some(&updated))?;
+            along.prepare(&mut pipe).await?;
             
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+            Ok((pipe, Some((medicine, updated, entry))))
+        }).await
     }
 
-    // pub async fn reduce_stock(&self, id: &str, amount: f64) -> Result<bool> {
-    //     if let Some(medicine) = self.get_by_id(id).await? {
-    //         if let Some(updated_medicine) = medicine.reduce_stock(amount) {
-    //             let key = format!("{}{}", self.prefix, id);
-    //             let value = serde_json::to_string(&updated_medicine)?;
-    //             
-    //             let mut conn = self.get_connection().await?;
-    //             let _: () = conn.set(&key, value).await?;
-    //             
-    //             Ok(true)
-    //         } else {
-    //             Ok(false)
-    //         }
-    //     } else {
-    //         Ok(false)
-    //     }
-    // }
+    fn save_in(&self, pipe: &mut Pipeline, medicine: &Medicine) -> Result<()> {
+        let key = format!("{}{}", self.prefix, medicine.id);
+        let value = serde_json::to_string(medicine)?;
+        pipe.set(&key, value).ignore();
+
+        Ok(())
+    }
+
+    async fn execute(&self, pipe: &Pipeline) -> Result<()> {
+        let mut conn = self.get_connection().await?;
+        let _: () = pipe.query_async(&mut conn).await?;
+
+        Ok(())
+    }
 } 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn create_test_connection() -> RedisConnection {
+        RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap()
+    }
 
     async fn create_test_repository() -> MedicineRepository {
         // Use a test Redis instance or mock
-        MedicineRepository::new("redis://localhost:6379", "test:medicine:".to_string()).unwrap()
+        MedicineRepository::new(create_test_connection(), "test:medicine:".to_string())
     }
 
     async fn create_empty_test_repository() -> MedicineRepository {
         // Use a unique prefix to ensure empty database
         let unique_prefix = format!("test:empty:{}:", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis());
-        MedicineRepository::new("redis://localhost:6379", unique_prefix).unwrap()
+        MedicineRepository::new(create_test_connection(), unique_prefix)
     }
 
     #[tokio::test]
@@ -146,6 +402,8 @@ mod tests {
             dose: 500.0,
             unit: "mg".to_string(),
             stock: 100.0,
+            prn: None,
+            active_ingredients: vec![],
         };
 
         let result = repo.create(api_medicine).await;
@@ -163,6 +421,8 @@ mod tests {
             dose: 500.0,
             unit: "mg".to_string(),
             stock: 100.0,
+            prn: None,
+            active_ingredients: vec![],
         };
 
         let id = repo.create(api_medicine).await.unwrap();
@@ -176,6 +436,28 @@ mod tests {
         assert_eq!(medicine.stock, 100.0);
     }
 
+    #[tokio::test]
+    async fn test_get_many() {
+        let repo = create_empty_test_repository().await;
+        let api_medicine = |name: &str| ApiMedicine {
+            name: name.to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 100.0,
+            prn: None,
+            active_ingredients: vec![],
+        };
+        let first = repo.create(api_medicine("First")).await.unwrap();
+        let second = repo.create(api_medicine("Second")).await.unwrap();
+
+        let medicines = repo.get_many(&[first.clone(), "non-existent-id".to_string(), second.clone(), first.clone()]).await.unwrap();
+
+        assert_eq!(medicines.len(), 2);
+        assert_eq!(medicines[&first].name, "First");
+        assert_eq!(medicines[&second].name, "Second");
+        assert!(repo.get_many(&[]).await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_get_by_id_not_found() {
         let repo = create_test_repository().await;
@@ -193,6 +475,8 @@ mod tests {
             dose: 500.0,
             unit: "mg".to_string(),
             stock: 100.0,
+            prn: None,
+            active_ingredients: vec![],
         };
 
         let id = repo.create(api_medicine).await.unwrap();
@@ -202,6 +486,8 @@ mod tests {
             dose: 750.0,
             unit: "mg".to_string(),
             stock: 150.0,
+            prn: None,
+            active_ingredients: vec![],
         };
 
         let result = repo.update(&id, updated_api_medicine).await;
@@ -222,6 +508,8 @@ mod tests {
             dose: 500.0,
             unit: "mg".to_string(),
             stock: 100.0,
+            prn: None,
+            active_ingredients: vec![],
         };
 
         let id = repo.create(api_medicine).await.unwrap();
@@ -247,22 +535,137 @@ mod tests {
             dose: 500.0,
             unit: "mg".to_string(),
             stock: 100.0,
+            prn: None,
+            active_ingredients: vec![],
         };
 
         let id = repo.create(api_medicine).await.unwrap();
         
-        let result = repo.add_stock(&id, 50.0).await;
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let result = repo.add_stock(&id, StockBatch::new(50.0, None, None, received), &redis::pipe()).await;
         assert!(result.is_ok());
         assert!(result.unwrap());
 
         let medicine = repo.get_by_id(&id).await.unwrap().unwrap();
         assert_eq!(medicine.stock, 150.0);
+        assert_eq!(medicine.batches.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_consume_stock() {
+        let repo = create_test_repository().await;
+        let api_medicine = ApiMedicine {
+            name: "Test Medicine".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 0.0,
+            prn: None,
+            active_ingredients: vec![],
+        };
+
+        let id = repo.create(api_medicine).await.unwrap();
+        let expiry = NaiveDate::from_ymd_opt(2030, 1, 1);
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        repo.add_stock(&id, StockBatch::new(10.0, Some("LOT".to_string()), expiry, received), &redis::pipe()).await.unwrap();
+
+        let result = repo.consume_stock(&id, 4.0, &redis::pipe()).await;
+        assert!(result.is_ok());
+        assert!(result.unwrap());
+
+        let medicine = repo.get_by_id(&id).await.unwrap().unwrap();
+        assert_eq!(medicine.stock, 6.0);
+        assert_eq!(medicine.batches[0].quantity, 6.0);
+    }
+
+    #[tokio::test]
+    async fn test_stock_ledger_and_reconcile() {
+        let ledger = StockLedgerRepository::new(create_test_connection(), "test:ledger:".to_string());
+        let repo = create_test_repository().await.with_ledger(ledger);
+        let api_medicine = ApiMedicine {
+            name: "Test Medicine".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 10.0,
+            prn: None,
+            active_ingredients: vec![],
+        };
+
+        let id = repo.create(api_medicine).await.unwrap();
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        repo.add_stock(&id, StockBatch::new(30.0, None, None, received), &redis::pipe()).await.unwrap();
+        repo.consume_stock(&id, 2.0, &redis::pipe()).await.unwrap();
+
+        let reconciliation = repo.reconcile(&id, 35.0, Some("Monthly count".to_string())).await.unwrap().unwrap();
+        assert_eq!(reconciliation.expected, 38.0);
+        assert_eq!(reconciliation.difference, -3.0);
+
+        let entries = repo.get_ledger(&id).await.unwrap();
+        let kinds: Vec<StockMovementKind> = entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, [StockMovementKind::Opening, StockMovementKind::Refill, StockMovementKind::DoseConsumed, StockMovementKind::Correction]);
+        assert_eq!(ledger_balance(&entries), 35.0);
+        assert_eq!(repo.get_by_id(&id).await.unwrap().unwrap().stock, 35.0);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_movements_are_all_booked() {
+        let ledger = StockLedgerRepository::new(create_test_connection(), "test:ledger:".to_string());
+        let repo = Arc::new(create_test_repository().await.with_ledger(ledger));
+        let api_medicine = ApiMedicine {
+            name: "Test Medicine".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 20.0,
+            prn: None,
+            active_ingredients: vec![],
+        };
+        let id = repo.create(api_medicine).await.unwrap();
+
+        let doses: Vec<_> = (0..8)
+            .map(|_| {
+                let (repo, id) = (repo.clone(), id.clone());
+                tokio::spawn(async move { repo.consume_stock(&id, 1.0, &redis::pipe()).await.unwrap() })
+            })
+            .collect();
+        for dose in doses {
+            assert!(dose.await.unwrap());
+        }
+
+        let entries = repo.get_ledger(&id).await.unwrap();
+        assert_eq!(entries.len(), 9);
+        assert_eq!(ledger_balance(&entries), 12.0);
+        assert_eq!(repo.get_by_id(&id).await.unwrap().unwrap().stock, 12.0);
+    }
+
+    #[tokio::test]
+    async fn test_consume_stock_stores_along() {
+        let repo = create_test_repository().await;
+        let api_medicine = ApiMedicine {
+            name: "Test Medicine".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 5.0,
+            prn: None,
+            active_ingredients: vec![],
+        };
+        let id = repo.create(api_medicine).await.unwrap();
+        let key = format!("test:along:{}", id);
+        let mut along = redis::pipe();
+        along.set(&key, "dose").ignore();
+
+        assert!(repo.consume_stock(&id, 1.0, &along).await.unwrap());
+
+        let mut conn = create_test_connection().get("test").await.unwrap();
+        let stored: Option<String> = conn.get(&key).await.unwrap();
+        assert_eq!(stored.as_deref(), Some("dose"));
+        assert!(!repo.consume_stock("non-existent-id", 1.0, &along).await.unwrap());
+        let _: () = conn.del(&key).await.unwrap();
     }
 
     #[tokio::test]
     async fn test_add_stock_medicine_not_found() {
         let repo = create_test_repository().await;
-        let result = repo.add_stock("non-existent-id", 50.0).await;
+        let received = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let result = repo.add_stock("non-existent-id", StockBatch::new(50.0, None, None, received), &redis::pipe()).await;
         
         assert!(result.is_ok());
         assert!(!result.unwrap());
@@ -279,12 +682,16 @@ mod tests {
                 dose: 100.0,
                 unit: "mg".to_string(),
                 stock: 50.0,
+                prn: None,
+                active_ingredients: vec![],
             },
             ApiMedicine {
                 name: "Medicine B".to_string(),
                 dose: 200.0,
                 unit: "mg".to_string(),
                 stock: 75.0,
+                prn: None,
+                active_ingredients: vec![],
             },
         ];
 
@@ -311,16 +718,7 @@ mod tests {
 
     #[test]
     fn test_medicine_repository_new() {
-        let result = MedicineRepository::new("redis://localhost:6379", "test:".to_string());
-        assert!(result.is_ok());
-        
-        let repo = result.unwrap();
+        let repo = MedicineRepository::new(create_test_connection(), "test:".to_string());
         assert_eq!(repo.prefix, "test:");
     }
-
-    #[test]
-    fn test_medicine_repository_new_invalid_redis_url() {
-        let result = MedicineRepository::new("invalid-url", "test:".to_string());
-        assert!(result.is_err());
-    }
 } 
\ No newline at end of file
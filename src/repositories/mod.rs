@@ -1,7 +1,56 @@
+pub mod redis_connection;
 pub mod medicine_repository;
+pub mod stock_ledger_repository;
 pub mod schedule_repository;
 pub mod dosage_history_repository;
+pub mod prescription_repository;
+pub mod profile_repository;
+pub mod grant_repository;
+pub mod api_key_repository;
+pub mod audit_repository;
+pub mod calendar_subscription_repository;
 
+pub use redis_connection::*;
 pub use medicine_repository::*;
+pub use stock_ledger_repository::*;
 pub use schedule_repository::*;
-pub use dosage_history_repository::*; 
\ No newline at end of file
+pub use dosage_history_repository::*;
+pub use prescription_repository::*;
+pub use profile_repository::*;
+pub use grant_repository::*;
+pub use api_key_repository::*;
+pub use audit_repository::*;
+pub use calendar_subscription_repository::*;
+
+/// The repositories of one profile, all keys are stored under the profile's prefix.
+/// They share the one Redis connection, so they're cheap to create per request.
+pub struct Repositories {
+    pub medicine_repo: MedicineRepository,
+    pub schedule_repo: MedicineScheduleRepository,
+    pub dosage_history_repo: DosageHistoryRepository,
+    pub prescription_repo: PrescriptionRepository,
+    pub audit_repo: AuditRepository,
+}
+
+impl Repositories {
+    pub fn new(redis: &RedisConnection, prefix: &str) -> Self {
+        Self {
+            medicine_repo: MedicineRepository::new(redis.clone(), format!("{}medicine:", prefix))
+                .with_ledger(StockLedgerRepository::new(redis.clone(), format!("{}ledger:", prefix))),
+            schedule_repo: MedicineScheduleRepository::new(redis.clone(), format!("{}schedule:", prefix)),
+            dosage_history_repo: DosageHistoryRepository::new(redis.clone(), format!("{}dosage:", prefix)),
+            prescription_repo: PrescriptionRepository::new(redis.clone(), format!("{}prescription:", prefix)),
+            audit_repo: AuditRepository::new(redis.clone(), format!("{}audit", prefix)),
+        }
+    }
+
+    /// Records the changes made through the medicine, schedule and dosage history repositories.
+    pub fn with_auditor(self, auditor: Auditor) -> Self {
+        Self {
+            medicine_repo: self.medicine_repo.with_auditor(auditor.clone()),
+            schedule_repo: self.schedule_repo.with_auditor(auditor.clone()),
+            dosage_history_repo: self.dosage_history_repo.with_auditor(auditor),
+            ..self
+        }
+    }
+} 
\ No newline at end of file
@@ -0,0 +1,227 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use redis::{AsyncCommands, Pipeline};
+use serde_json;
+use crate::models::{Prescription, ApiPrescription, MedicineId, NoRefillLeft};
+use crate::repositories::{AlongWithStock, InstrumentedConnection, RedisConnection};
+
+pub struct PrescriptionRepository {
+    redis: RedisConnection,
+    prefix: String,
+}
+
+impl PrescriptionRepository {
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
+            prefix,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("prescription").await
+    }
+
+    pub async fn create(&self, api_prescription: ApiPrescription) -> Result<String> {
+        let prescription = api_prescription.to_prescription();
+        self.save(&prescription).await?;
+
+        Ok(prescription.id)
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<Prescription>> {
+        let mut conn = self.get_connection().await?;
+        let pattern = format!("{}*", self.prefix);
+        let keys: Vec<String> = conn.keys(&pattern).await?;
+
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        let mut prescriptions = Vec::new();
+
+        for json_str in values.into_iter().flatten() {
+            if let Ok(prescription) = serde_json::from_str::<Prescription>(&json_str) {
+                prescriptions.push(prescription);
+            }
+        }
+
+        prescriptions.sort_by_key(|prescription| prescription.expiry_date);
+        Ok(prescriptions)
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> Result<Option<Prescription>> {
+        let key = format!("{}{}", self.prefix, id);
+        let mut conn = self.get_connection().await?;
+        let value: Option<String> = conn.get(&key).await?;
+
+        match value {
+            Some(json_str) => {
+                let prescription = serde_json::from_str::<Prescription>(&json_str)?;
+                Ok(Some(prescription))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn update(&self, id: &str, api_prescription: ApiPrescription) -> Result<bool> {
+        let prescription = api_prescription.to_prescription_with_id(id.to_string());
+        self.save(&prescription).await?;
+
+        Ok(true)
+    }
+
+    pub async fn save(&self, prescription: &Prescription) -> Result<()> {
+        let key = format!("{}{}", self.prefix, prescription.id);
+        let value = serde_json::to_string(prescription)?;
+
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.set(&key, value).await?;
+
+        Ok(())
+    }
+
+    /// Uses up one refill of the prescription, along with the stock it fills.
+    pub fn refill(&self, id: &str, medicine_id: &MedicineId, date: NaiveDate) -> Refill<'_> {
+        Refill {
+            repo: self,
+            id: id.to_string(),
+            medicine_id: medicine_id.clone(),
+            date,
+        }
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        let key = format!("{}{}", self.prefix, id);
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.del(&key).await?;
+
+        Ok(())
+    }
+}
+
+/// Using up a refill of a prescription, which fails with `NoRefillLeft` when the
+/// prescription has none left, expired or is not for the medicine filled.
+pub struct Refill<'a> {
+    repo: &'a PrescriptionRepository,
+    id: String,
+    medicine_id: MedicineId,
+    date: NaiveDate,
+}
+
+impl AlongWithStock for Refill<'_> {
+    fn keys(&self) -> Vec<String> {
+        vec![format!("{}{}", self.repo.prefix, self.id)]
+    }
+
+    async fn prepare(&self, pipe: &mut Pipeline) -> Result<()> {
+        let refilled = self.repo.get_by_id(&self.id).await?
+            .filter(|prescription| prescription.medicine_id == self.medicine_id)
+            .and_then(|prescription| prescription.use_refill(self.date))
+            .ok_or_else(|| NoRefillLeft(self.id.clone()))?;
+        let value = serde_json::to_string(&refilled)?;
+        pipe.set(format!("{}{}", self.repo.prefix, refilled.id), value).ignore();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::models::{ApiMedicine, StockBatch};
+    use crate::repositories::MedicineRepository;
+
+    fn create_test_repository() -> PrescriptionRepository {
+        let redis = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+        PrescriptionRepository::new(redis, "test:prescription:".to_string())
+    }
+
+    fn api_prescription() -> ApiPrescription {
+        ApiPrescription {
+            medicine_id: "med1".to_string(),
+            prescriber: "Dr. Jansen".to_string(),
+            issue_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            quantity_per_fill: 30.0,
+            refills_remaining: 2,
+            expiry_date: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_get_by_id() {
+        let repo = create_test_repository();
+
+        let id = repo.create(api_prescription()).await.unwrap();
+        let prescription = repo.get_by_id(&id).await.unwrap().unwrap();
+
+        assert_eq!(prescription.id, id);
+        assert_eq!(prescription.medicine_id, "med1");
+        assert_eq!(prescription.refills_remaining, 2);
+        assert!(repo.get_all().await.unwrap().iter().any(|prescription| prescription.id == id));
+
+        repo.delete(&id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_by_id_not_found() {
+        let repo = create_test_repository();
+
+        assert!(repo.get_by_id("non-existent-id").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_save_used_refill() {
+        let repo = create_test_repository();
+        let id = repo.create(api_prescription()).await.unwrap();
+        let prescription = repo.get_by_id(&id).await.unwrap().unwrap();
+
+        let refilled = prescription.use_refill(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()).unwrap();
+        repo.save(&refilled).await.unwrap();
+
+        assert_eq!(repo.get_by_id(&id).await.unwrap().unwrap().refills_remaining, 1);
+
+        repo.delete(&id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refill_along_with_stock_takes_the_last_refill_once() {
+        let repo = create_test_repository();
+        let redis = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+        let medicine_repo = MedicineRepository::new(redis, "test:medicine:".to_string());
+        let medicine_id = medicine_repo.create(ApiMedicine {
+            name: "Test Medicine".to_string(),
+            dose: 500.0,
+            unit: "mg".to_string(),
+            stock: 0.0,
+            prn: None,
+            active_ingredients: vec![],
+        }).await.unwrap();
+        let id = repo.create(ApiPrescription { medicine_id: medicine_id.clone(), refills_remaining: 1, ..api_prescription() }).await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+
+        let refill = repo.refill(&id, &medicine_id, date);
+        let fill = || medicine_repo.add_stock(&medicine_id, StockBatch::new(30.0, None, None, date), &refill);
+        let (first, second) = tokio::join!(fill(), fill());
+
+        assert_eq!([&first, &second].iter().filter(|fill| fill.is_ok()).count(), 1);
+        assert!([first, second].into_iter().any(|fill| fill.is_err_and(|e| e.is::<NoRefillLeft>())));
+        assert_eq!(repo.get_by_id(&id).await.unwrap().unwrap().refills_remaining, 0);
+        assert_eq!(medicine_repo.get_by_id(&medicine_id).await.unwrap().unwrap().stock, 30.0);
+
+        repo.delete(&id).await.unwrap();
+        medicine_repo.delete(&medicine_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let repo = create_test_repository();
+        let id = repo.create(api_prescription()).await.unwrap();
+
+        repo.delete(&id).await.unwrap();
+
+        assert!(repo.get_by_id(&id).await.unwrap().is_none());
+    }
+}
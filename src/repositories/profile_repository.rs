@@ -0,0 +1,93 @@
+use anyhow::Result;
+use redis::AsyncCommands;
+use serde_json;
+use crate::models::{Profile, ApiProfile, ProfileId};
+use crate::repositories::{InstrumentedConnection, RedisConnection};
+
+pub struct ProfileRepository {
+    redis: RedisConnection,
+    prefix: String,
+}
+
+impl ProfileRepository {
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
+            prefix,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("profile").await
+    }
+
+    pub async fn create(&self, api_profile: ApiProfile) -> Result<ProfileId> {
+        let profile = api_profile.to_profile();
+        self.save(&profile).await?;
+
+        Ok(profile.id)
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<Profile>> {
+        let mut conn = self.get_connection().await?;
+        let pattern = format!("{}*", self.prefix);
+        let keys: Vec<String> = conn.keys(&pattern).await?;
+
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        let mut profiles = Vec::new();
+
+        for json_str in values.into_iter().flatten() {
+            if let Ok(profile) = serde_json::from_str::<Profile>(&json_str) {
+                profiles.push(profile);
+            }
+        }
+
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(profiles)
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> Result<Option<Profile>> {
+        let key = format!("{}{}", self.prefix, id);
+        let mut conn = self.get_connection().await?;
+        let value: Option<String> = conn.get(&key).await?;
+
+        match value {
+            Some(json_str) => {
+                let profile = serde_json::from_str::<Profile>(&json_str)?;
+                Ok(Some(profile))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn update(&self, id: &str, api_profile: ApiProfile) -> Result<bool> {
+        let profile = api_profile.to_profile_with_id(id.to_string());
+        self.save(&profile).await?;
+
+        Ok(true)
+    }
+
+    async fn save(&self, profile: &Profile) -> Result<()> {
+        let key = format!("{}{}", self.prefix, profile.id);
+        let value = serde_json::to_string(profile)?;
+
+        let mut conn = self.get_connection().await?;
+        let _: () = conn.set(&key, value).await?;
+
+        Ok(())
+    }
+
+    /// Deletes the profile together with all data stored under `data_prefix`.
+    pub async fn delete(&self, id: &str, data_prefix: &str) -> Result<()> {
+        let mut conn = self.get_connection().await?;
+        let mut keys: Vec<String> = conn.keys(format!("{}*", data_prefix)).await?;
+        keys.push(format!("{}{}", self.prefix, id));
+        let _: () = conn.del(&keys).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use redis::{Arg, AsyncConnectionConfig, Client, Cmd, Pipeline, RedisFuture, Value};
+use redis::aio::{ConnectionLike, ConnectionManager, ConnectionManagerConfig};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tracing::Instrument;
+
+/// How often a watched transaction is tried before giving up on concurrent changes.
+const MAX_TRANSACTION_ATTEMPTS: usize = 10;
+
+/// A multiplexed connection to Redis shared by all repositories. It connects on
+/// first use and reconnects by itself when the connection drops. Clones share the
+/// same connection.
+#[derive(Clone)]
+pub struct RedisConnection {
+    client: Client,
+    connection_timeout: Duration,
+    response_timeout: Duration,
+    manager: Arc<OnceCell<ConnectionManager>>,
+}
+
+impl RedisConnection {
+    pub fn new(redis_url: &str, connection_timeout: Duration, response_timeout: Duration) -> Result<Self> {
+        let client = Client::open(redis_url)?;
+        Ok(Self {
+            client,
+            connection_timeout,
+            response_timeout,
+            manager: Arc::new(OnceCell::new()),
+        })
+    }
+
+    /// A handle on the shared connection for the repository of `entity`, connecting
+    /// first if that hasn't happened yet.
+    pub async fn get(&self, entity: &'static str) -> Result<InstrumentedConnection> {
+        let started = Instant::now();
+        let manager = self.manager.get_or_try_init(|| {
+            // Retries after 1 and 2 seconds, the default backoff grows to minutes
+            let config = ConnectionManagerConfig::new()
+                .set_factor(2)
+                .set_max_delay(2000)
+                .set_number_of_retries(2)
+                .set_connection_timeout(self.connection_timeout)
+                .set_response_timeout(self.response_timeout);
+            ConnectionManager::new_with_config(self.client.clone(), config)
+        }).await;
+        match manager {
+            Ok(manager) => Ok(InstrumentedConnection { inner: manager.clone(), entity }),
+            Err(e) => {
+                record_operation(entity, "CONNECT".to_string(), started.elapsed(), false);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Runs a transaction that only commits when none of `keys` changed after they
+    /// were watched, retrying it otherwise. `attempt` reads what it needs, after the
+    /// keys are watched, and returns the writes to make with its result.
+    ///
+    /// The transaction gets a connection of its own, a WATCH on the shared connection
+    /// would be cleared by the transactions of other requests.
+    pub async fn watched<T, F, Fut>(&self, entity: &'static str, keys: &[String], mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(Pipeline, T)>>,
+    {
+        let config = AsyncConnectionConfig::new()
+            .set_connection_timeout(self.connection_timeout)
+            .set_response_timeout(self.response_timeout);
+        let connection = self.client.get_multiplexed_async_connection_with_config(&config).await?;
+        let mut conn = InstrumentedConnection { inner: connection, entity };
+        for _ in 0..MAX_TRANSACTION_ATTEMPTS {
+            let _: () = redis::cmd("WATCH").arg(keys).query_async(&mut conn).await?;
+            let (mut pipe, value) = match attempt().await {
+                Ok((pipe, value)) if pipe.cmd_iter().next().is_some() => (pipe, value),
+                Ok((_, value)) => return Ok(value),
+                Err(e) => return Err(e),
+            };
+            let committed: Option<()> = pipe.atomic().query_async(&mut conn).await?;
+            if committed.is_some() {
+                return Ok(value);
+            }
+        }
+        Err(anyhow::anyhow!("{} changed concurrently {} times in a row", entity, MAX_TRANSACTION_ATTEMPTS))
+    }
+
+    /// Pings Redis, returning how long it took to answer.
+    pub async fn ping(&self) -> Result<Duration> {
+        let started = Instant::now();
+        let mut conn = self.get("health").await?;
+        let _: String = redis::cmd("PING").query_async(&mut conn).await?;
+        Ok(started.elapsed())
+    }
+}
+
+/// The shared connection, recording the latency and errors of each command under
+/// the entity of the repository using it.
+pub struct InstrumentedConnection<C = ConnectionManager> {
+    inner: C,
+    entity: &'static str,
+}
+
+impl<C: ConnectionLike + Send> ConnectionLike for InstrumentedConnection<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let operation = command_name(cmd);
+        let span = tracing::info_span!("redis", entity = self.entity, operation = %operation);
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = self.inner.req_packed_command(cmd).await;
+            record_operation(self.entity, operation, started.elapsed(), result.is_ok());
+            result
+        }.instrument(span))
+    }
+
+    fn req_packed_commands<'a>(&'a mut self, cmd: &'a Pipeline, offset: usize, count: usize) -> RedisFuture<'a, Vec<Value>> {
+        let span = tracing::info_span!("redis", entity = self.entity, operation = "PIPELINE");
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = self.inner.req_packed_commands(cmd, offset, count).await;
+            record_operation(self.entity, "PIPELINE".to_string(), started.elapsed(), result.is_ok());
+            result
+        }.instrument(span))
+    }
+
+    fn get_db(&self) -> i64 {
+        self.inner.get_db()
+    }
+}
+
+fn command_name(cmd: &Cmd) -> String {
+    match cmd.args_iter().next() {
+        Some(Arg::Simple(name)) => String::from_utf8_lossy(name).to_uppercase(),
+        _ => "UNKNOWN".to_string(),
+    }
+}
+
+fn record_operation(entity: &'static str, operation: String, duration: Duration, success: bool) {
+    metrics::histogram!("repository_operation_duration_seconds", "entity" => entity, "operation" => operation.clone())
+        .record(duration.as_secs_f64());
+    if !success {
+        metrics::counter!("repository_errors_total", "entity" => entity, "operation" => operation).increment(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redis_connection_new() {
+        let result = RedisConnection::new("redis://localhost:6379", Duration::from_secs(1), Duration::from_secs(1));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_redis_connection_new_invalid_redis_url() {
+        let result = RedisConnection::new("invalid-url", Duration::from_secs(1), Duration::from_secs(1));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_command_name() {
+        assert_eq!(command_name(&redis::cmd("mget")), "MGET");
+        assert_eq!(command_name(redis::cmd("ZADD").arg("key")), "ZADD");
+    }
+}
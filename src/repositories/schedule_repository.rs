@@ -1,26 +1,44 @@
 use anyhow::Result;
-use redis::{AsyncCommands, Client, aio::Connection};
+use redis::{AsyncCommands, Pipeline};
 use serde_json;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
 use crate::models::{
-    MedicineSchedule, ApiMedicineSchedule, DailySchedule, DailyScheduleWithDate
+    MedicineSchedule, ApiMedicineSchedule, DailySchedule, DailyScheduleWithDate, DailyScheduleWithDateV2, AuditAction
 };
+use crate::repositories::{Auditor, DosageHistoryRepository, InstrumentedConnection, MedicineRepository, RedisConnection};
 
 pub struct MedicineScheduleRepository {
-    redis_client: Client,
+    redis: RedisConnection,
     prefix: String,
+    auditor: Option<Auditor>,
 }
 
 impl MedicineScheduleRepository {
-    pub fn new(redis_url: &str, prefix: String) -> Result<Self> {
-        let client = Client::open(redis_url)?;
-        Ok(Self {
-            redis_client: client,
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
             prefix,
-        })
+            auditor: None,
+        }
+    }
+
+    pub fn with_auditor(self, auditor: Auditor) -> Self {
+        Self {
+            auditor: Some(auditor),
+            ..self
+        }
+    }
+
+    fn audit(&self, pipe: &mut Pipeline, id: &str, action: AuditAction, before: Option<&MedicineSchedule>, after: Option<&MedicineSchedule>) -> Result<()> {
+        match &self.auditor {
+            Some(auditor) => auditor.record(pipe, "schedule", id, action, before, after),
+            None => Ok(()),
+        }
     }
 
-    async fn get_connection(&self) -> Result<Connection> {
-        self.redis_client.get_async_connection().await.map_err(|e| anyhow::anyhow!(e))
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("schedule").await
     }
 
     pub async fn create(&self, api_schedule: ApiMedicineSchedule) -> Result<String> {
@@ -28,12 +46,38 @@ impl MedicineScheduleRepository {
         let key = format!("{}{}", self.prefix, schedule.id);
         let value = serde_json::to_string(&schedule)?;
         
+        let mut pipe = redis::pipe();
+        pipe.atomic().set(&key, value).ignore();
+        self.audit(&mut pipe, &schedule.id, AuditAction::Create, None, Some(&schedule))?;
+        
         let mut conn = self.get_connection().await?;
-        let _: () = conn.set(&key, value).await?;
+        let _: () = pipe.query_async(&mut conn).await?;
         
         Ok(schedule.id)
     }
 
+    /// Creates the schedules in one transaction, so either all or none are stored.
+    pub async fn create_all(&self, api_schedules: &[ApiMedicineSchedule]) -> Result<Vec<String>> {
+        let schedules: Vec<MedicineSchedule> = api_schedules.iter().map(|api_schedule| api_schedule.to_schedule()).collect();
+        if schedules.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for schedule in &schedules {
+            let key = format!("{}{}", self.prefix, schedule.id);
+            let value = serde_json::to_string(schedule)?;
+            pipe.set(&key, value).ignore();
+            self.audit(&mut pipe, &schedule.id, AuditAction::Create, None, Some(schedule))?;
+        }
+        
+        let mut conn = self.get_connection().await?;
+        let _: () = pipe.query_async(&mut conn).await?;
+        
+        Ok(schedules.into_iter().map(|schedule| schedule.id).collect())
+    }
+
     pub async fn get_all(&self) -> Result<Vec<MedicineSchedule>> {
         let mut conn = self.get_connection().await?;
         let pattern = format!("{}*", self.prefix);
@@ -46,11 +90,9 @@ impl MedicineScheduleRepository {
         let values: Vec<Option<String>> = conn.mget(&keys).await?;
         let mut schedules = Vec::new();
         
-        for value in values {
-            if let Some(json_str) = value {
-                if let Ok(schedule) = serde_json::from_str::<MedicineSchedule>(&json_str) {
-                    schedules.push(schedule);
-                }
+        for json_str in values.into_iter().flatten() {
+            if let Ok(schedule) = serde_json::from_str::<MedicineSchedule>(&json_str) {
+                schedules.push(schedule);
             }
         }
         
@@ -74,19 +116,29 @@ impl MedicineScheduleRepository {
 
     pub async fn update(&self, id: &str, api_schedule: ApiMedicineSchedule) -> Result<bool> {
         let schedule = api_schedule.to_schedule_with_id(id.to_string());
+        let existing = self.get_by_id(id).await?;
         let key = format!("{}{}", self.prefix, id);
         let value = serde_json::to_string(&schedule)?;
         
+        let mut pipe = redis::pipe();
+        pipe.atomic().set(&key, value).ignore();
+        self.audit(&mut pipe, id, AuditAction::Update, existing.as_ref(), Some(&schedule))?;
+        
         let mut conn = self.get_connection().await?;
-        let _: () = conn.set(&key, value).await?;
+        let _: () = pipe.query_async(&mut conn).await?;
         
         Ok(true)
     }
 
     pub async fn delete(&self, id: &str) -> Result<()> {
         let key = format!("{}{}", self.prefix, id);
+        let existing = self.get_by_id(id).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        self.audit(&mut pipe, id, AuditAction::Delete, existing.as_ref(), None)?;
+        
         let mut conn = self.get_connection().await?;
-        let _: () = conn.del(&key).await?;
+        let _: () = pipe.query_async(&mut conn).await?;
         
         Ok(())
     }
@@ -99,16 +151,17 @@ impl MedicineScheduleRepository {
         let mut time_groups: std::collections::HashMap<String, Vec<MedicineSchedule>> = std::collections::HashMap::new();
         
         for schedule in schedules {
-            time_groups.entry(schedule.time.clone()).or_insert_with(Vec::new).push(schedule);
+            time_groups.entry(schedule.time.clone()).or_default().push(schedule);
         }
         
+        // The medicines of all schedules in one round trip
+        let medicine_ids: Vec<_> = time_groups.values().flatten().map(|schedule| schedule.medicine_id.clone()).collect();
+        let medicines = medicine_repo.get_many(&medicine_ids).await?;
+        
         for (time, schedules) in time_groups {
-            let mut medicines_with_amounts = Vec::new();
-            
-            for schedule in schedules {
-                let medicine = medicine_repo.get_by_id(&schedule.medicine_id).await?;
-                medicines_with_amounts.push((medicine, schedule.amount));
-            }
+            let medicines_with_amounts = schedules.iter()
+                .map(|schedule| (medicines.get(&schedule.medicine_id).cloned(), schedule.amount))
+                .collect();
             
             let daily_schedule = DailySchedule::new(time, medicines_with_amounts);
             daily_schedules.push(daily_schedule);
@@ -122,4 +175,21 @@ impl MedicineScheduleRepository {
         let schedules = self.get_daily_schedule(date, medicine_repo).await?;
         Ok(DailyScheduleWithDate::new(date.to_string(), schedules))
     }
+
+    /// The daily schedule with named fields and whether each dose was taken.
+    pub async fn get_daily_schedule_v2(&self, date: NaiveDate, timezone: Tz, medicine_repo: &MedicineRepository, dosage_history_repo: &DosageHistoryRepository) -> Result<DailyScheduleWithDateV2> {
+        let mut range = self.get_schedule_range(date, date, timezone, medicine_repo, dosage_history_repo).await?;
+        Ok(range.remove(0))
+    }
+
+    /// The daily schedules from `from` up to and including `to`, dates in `timezone`.
+    /// The schedules, medicines and doses are each fetched once for the whole range.
+    pub async fn get_schedule_range(&self, from: NaiveDate, to: NaiveDate, timezone: Tz, medicine_repo: &MedicineRepository, dosage_history_repo: &DosageHistoryRepository) -> Result<Vec<DailyScheduleWithDateV2>> {
+        let schedules = self.get_all().await?;
+        let medicine_ids: Vec<_> = schedules.iter().map(|schedule| schedule.medicine_id.clone()).collect();
+        let medicines = medicine_repo.get_many(&medicine_ids).await?;
+        let history = dosage_history_repo.get_all().await?;
+        
+        Ok(DailyScheduleWithDateV2::range(from, to, &schedules, &medicines, &history, timezone))
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use redis::{AsyncCommands, Pipeline};
+use serde_json;
+use crate::models::LedgerEntry;
+use crate::repositories::{InstrumentedConnection, RedisConnection};
+
+/// The stock movements of each medicine, kept in a list per medicine in the order
+/// they happened.
+pub struct StockLedgerRepository {
+    redis: RedisConnection,
+    prefix: String,
+}
+
+impl StockLedgerRepository {
+    pub fn new(redis: RedisConnection, prefix: String) -> Self {
+        Self {
+            redis,
+            prefix,
+        }
+    }
+
+    async fn get_connection(&self) -> Result<InstrumentedConnection> {
+        self.redis.get("ledger").await
+    }
+
+    /// Key of the ledger of a medicine, watched while booking a movement.
+    pub fn key(&self, medicine_id: &str) -> String {
+        format!("{}{}", self.prefix, medicine_id)
+    }
+
+    /// Appends the entry as part of `pipe`, so it is stored together with the stock.
+    pub fn append_to(&self, pipe: &mut Pipeline, entry: &LedgerEntry) -> Result<()> {
+        let value = serde_json::to_string(entry)?;
+        pipe.rpush(self.key(&entry.medicine_id), value).ignore();
+
+        Ok(())
+    }
+
+    pub async fn get_by_medicine_id(&self, medicine_id: &str) -> Result<Vec<LedgerEntry>> {
+        let key = self.key(medicine_id);
+        let mut conn = self.get_connection().await?;
+        let values: Vec<String> = conn.lrange(&key, 0, -1).await?;
+
+        Ok(values.iter()
+            .filter_map(|json_str| serde_json::from_str::<LedgerEntry>(json_str).ok())
+            .collect())
+    }
+
+    pub fn delete_in(&self, pipe: &mut Pipeline, medicine_id: &str) {
+        pipe.del(self.key(medicine_id)).ignore();
+    }
+}
@@ -0,0 +1,104 @@
+use axum::{
+    body::Body,
+    http::{HeaderName, Request, Response},
+};
+use std::time::Duration;
+use tracing::Span;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use crate::config::{Config, LogFormat};
+
+/// Header carrying the id of a request, taken from the client or generated.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Sets up logging and, when built with the `otlp` feature and an endpoint is
+/// configured, exporting spans to an OpenTelemetry collector. The exporter is
+/// flushed when the returned guard is dropped.
+pub fn init_tracing(config: &Config) -> anyhow::Result<TracingGuard> {
+    let json = config.log_format == LogFormat::Json;
+    let registry = tracing_subscriber::registry()
+        .with(EnvFilter::new(&config.log_level))
+        .with((!json).then(fmt::layer))
+        .with(json.then(|| fmt::layer().json()));
+
+    #[cfg(feature = "otlp")]
+    {
+        let provider = config.otlp_endpoint.as_deref()
+            .map(|endpoint| otlp::tracer_provider(endpoint, &config.service_name))
+            .transpose()?;
+        let layer = provider.as_ref().map(|provider| otlp::layer(provider, &config.service_name));
+        registry.with(layer).init();
+        Ok(TracingGuard { provider })
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    {
+        registry.init();
+        Ok(TracingGuard {})
+    }
+}
+
+pub struct TracingGuard {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = &self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Could not flush the exported spans: {}", e);
+            }
+        }
+    }
+}
+
+/// The span all events of a request are logged in. The route is recorded once the
+/// request is routed, the status and latency when the response is ready. The path
+/// itself is left out, as some paths contain secrets like calendar feed tokens.
+pub fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request.headers().get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        request_id,
+        route = tracing::field::Empty,
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+    )
+}
+
+pub fn record_response<B>(response: &Response<B>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_secs_f64() * 1000.0);
+    tracing::info!("Request finished");
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::{trace::TracerProvider as _, KeyValue};
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+    use tracing::Subscriber;
+    use tracing_subscriber::{registry::LookupSpan, Layer};
+
+    pub fn tracer_provider(endpoint: &str, service_name: &str) -> anyhow::Result<TracerProvider> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?;
+        Ok(TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([KeyValue::new("service.name", service_name.to_string())]))
+            .build())
+    }
+
+    pub fn layer<S>(provider: &TracerProvider, service_name: &str) -> impl Layer<S>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name.to_string()))
+    }
+}